@@ -1,17 +1,1146 @@
-// Return edit distance
+use crate::sam::Op;
+/// Return edit distance.
+/// The common prefix and suffix are stripped before the DP, and the DP keeps only a single row
+/// along the shorter sequence. Thus, the memory usage is O(min(|x1|,|x2|)),
+/// while the time is O(|x1||x2|) in the worst case.
 pub fn edit_dist(x1: &[u8], x2: &[u8]) -> u32 {
+    let mut row = vec![];
+    edit_dist_with(&mut row, x1, x2)
+}
+
+/// Return the full DP table of the edit distance, where `dp[i][j]` is the edit distance between `x1[..i]` and `x2[..j]`.
+/// Thus, the table has `|x1|+1` rows and `|x2|+1` columns, and `dp[|x1|][|x2|]` equals to [`edit_dist`].
+/// As the memory usage is O(|x1||x2|), this is for visualization and debugging. Use [`edit_dist`] for the distance.
+pub fn edit_dist_matrix(x1: &[u8], x2: &[u8]) -> Vec<Vec<u32>> {
     let mut dp = vec![vec![0; x2.len() + 1]; x1.len() + 1];
     for (i, row) in dp.iter_mut().enumerate() {
         row[0] = i as u32;
     }
-    for j in 0..=x2.len() {
-        dp[0][j] = j as u32;
+    for (j, cell) in dp[0].iter_mut().enumerate() {
+        *cell = j as u32;
+    }
+    for (i, &x1_b) in x1.iter().enumerate() {
+        for (j, &x2_b) in x2.iter().enumerate() {
+            let mismatch = (x1_b != x2_b) as u32;
+            dp[i + 1][j + 1] = (dp[i][j] + mismatch)
+                .min(dp[i][j + 1] + 1)
+                .min(dp[i + 1][j] + 1);
+        }
+    }
+    dp
+}
+
+/// Return the edit distance divided by the length of the longer sequence.
+/// The denominator is `max(|x1|,|x2|)`, so that the value is always in [0,1].
+/// If both sequences are empty, return 0.
+pub fn normalized_edit_distance(x1: &[u8], x2: &[u8]) -> f64 {
+    let len = x1.len().max(x2.len());
+    if len == 0 {
+        0.
+    } else {
+        edit_dist(x1, x2) as f64 / len as f64
+    }
+}
+
+/// Return the identity between two sequences, i.e., `1 - normalized_edit_distance(x1,x2)`.
+pub fn identity(x1: &[u8], x2: &[u8]) -> f64 {
+    1. - normalized_edit_distance(x1, x2)
+}
+
+/// An aligner holding the DP buffer, so that the allocation is reused across calls.
+/// The results are the same as the corresponding free functions.
+#[derive(Debug, Clone, Default)]
+pub struct Aligner {
+    row: Vec<u32>,
+}
+
+impl Aligner {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// Return edit distance. See [`edit_dist`].
+    pub fn edit_dist(&mut self, x1: &[u8], x2: &[u8]) -> u32 {
+        edit_dist_with(&mut self.row, x1, x2)
+    }
+}
+
+/// Return the matrix of the pairwise edit distances. Only the upper triangle is computed,
+/// reusing the DP buffer of an [`Aligner`], and mirrored to the lower one. The diagonal is zero.
+pub fn distance_matrix(seqs: &[&[u8]]) -> Vec<Vec<u32>> {
+    let mut aligner = Aligner::new();
+    let mut matrix = vec![vec![0; seqs.len()]; seqs.len()];
+    for (i, x1) in seqs.iter().enumerate() {
+        for (j, x2) in seqs.iter().enumerate().skip(i + 1) {
+            let dist = aligner.edit_dist(x1, x2);
+            matrix[i][j] = dist;
+            matrix[j][i] = dist;
+        }
+    }
+    matrix
+}
+
+/// Convert two gapped sequences into CIGAR operations. The gaps should be `-`.
+/// The `query` is the first sequence and the `refr` is the second, so
+/// a base aligned to a gap in `refr` is an insertion, and vice versa.
+/// If `distinguish_matches` is true, aligned columns are `=`/`X`; otherwise `M`.
+/// Columns with gaps in both sequences are ignored.
+/// Panics if the two sequences have different lengths.
+/// This is the inverse of `bam::recover_alignment`.
+pub fn cigar_from_gapped(query: &[u8], refr: &[u8], distinguish_matches: bool) -> Vec<Op> {
+    assert_eq!(query.len(), refr.len());
+    let mut ops: Vec<Op> = vec![];
+    for (&q, &r) in query.iter().zip(refr.iter()) {
+        let op = match (q == b'-', r == b'-') {
+            (true, true) => continue,
+            (true, false) => Op::Deletion(1),
+            (false, true) => Op::Insertion(1),
+            (false, false) if !distinguish_matches => Op::Align(1),
+            (false, false) if q.eq_ignore_ascii_case(&r) => Op::Match(1),
+            (false, false) => Op::Mismatch(1),
+        };
+        push_op(&mut ops, op);
+    }
+    ops
+}
+
+// Push `op` into `ops`, extending the last operation if they are the same kind.
+fn push_op(ops: &mut Vec<Op>, op: Op) {
+    use Op::*;
+    match (ops.last_mut(), op) {
+        (Some(Align(l)), Align(m))
+        | (Some(Insertion(l)), Insertion(m))
+        | (Some(Deletion(l)), Deletion(m))
+        | (Some(Skipped(l)), Skipped(m))
+        | (Some(SoftClip(l)), SoftClip(m))
+        | (Some(HardClip(l)), HardClip(m))
+        | (Some(Padding(l)), Padding(m))
+        | (Some(Match(l)), Match(m))
+        | (Some(Mismatch(l)), Mismatch(m)) => *l += m,
+        _ => ops.push(op),
+    }
+}
+
+/// Semi-global alignment, where the gaps at the start and the end of `x2` are free.
+/// The `x1` should be the query and the `x2` should be the reference, so this is the alignment of
+/// a short read `x1` into a long reference `x2`. The `match_score` is added for each match,
+/// and the `mismatch` and the `gap` for each mismatch and gap(usually negative).
+/// Return the optimal score and the alignment in `=`/`X`/`I`/`D`. The free end gaps on the
+/// reference are the leading and trailing `D`s, so the alignment starts at `x2[l]`
+/// if the first operation is `Deletion(l)`.
+pub fn semi_global(
+    x1: &[u8],
+    x2: &[u8],
+    match_score: i32,
+    mismatch: i32,
+    gap: i32,
+) -> (i32, Vec<Op>) {
+    let score = |a: u8, b: u8| {
+        if a.eq_ignore_ascii_case(&b) {
+            match_score
+        } else {
+            mismatch
+        }
+    };
+    align_dp(x1, x2, score, gap, true)
+}
+
+/// Global alignment with linear gap penalty. The parameters and the returned value are the same as
+/// [`semi_global`], except that all the gaps are penalized.
+pub fn global(x1: &[u8], x2: &[u8], match_score: i32, mismatch: i32, gap: i32) -> (i32, Vec<Op>) {
+    let score = |a: u8, b: u8| {
+        if a.eq_ignore_ascii_case(&b) {
+            match_score
+        } else {
+            mismatch
+        }
+    };
+    align_dp(x1, x2, score, gap, false)
+}
+
+/// The score of [`global`] without the traceback. Only two rows of the DP table are kept,
+/// so the memory is O(|x2|) instead of O(|x1||x2|).
+pub fn global_score(x1: &[u8], x2: &[u8], match_score: i32, mismatch: i32, gap: i32) -> i32 {
+    let mut prev: Vec<i32> = (0..=x2.len() as i32).map(|j| j * gap).collect();
+    let mut current = vec![0; x2.len() + 1];
+    for (i, &x1_b) in x1.iter().enumerate() {
+        current[0] = (i as i32 + 1) * gap;
+        for (j, &x2_b) in x2.iter().enumerate() {
+            let score = if x1_b.eq_ignore_ascii_case(&x2_b) {
+                match_score
+            } else {
+                mismatch
+            };
+            let diag = prev[j] + score;
+            let ins = prev[j + 1] + gap;
+            let del = current[j] + gap;
+            current[j + 1] = diag.max(ins).max(del);
+        }
+        std::mem::swap(&mut prev, &mut current);
+    }
+    prev[x2.len()]
+}
+
+/// Place a short `read` into the `reference` with at most `band` edits.
+/// Return the start position (0-based) of the placement on the reference and the alignment in `=`/`X`/`I`/`D`,
+/// without the free end gaps on the reference. If the read needs more than `band` edits everywhere, return `None`.
+/// As a path with at most `band` edits never leaves the `band` diagonals around its start,
+/// only the cells with the edit distance not more than `band` are computed(Ukkonen's cut-off),
+/// and the alignment is recovered by [`semi_global`] on the window ending at the best placement.
+/// Thus, the time is O(band|reference| + |read|(|read|+band)).
+/// It returns `None` at once if the reference is too short to place the read within `band` edits,
+/// and stops scanning the reference once an exact placement is found, as no placement can be better.
+/// Otherwise, the whole reference is scanned.
+pub fn place_read(read: &[u8], reference: &[u8], band: usize) -> Option<(usize, Vec<Op>)> {
+    let n = read.len();
+    if reference.len() + band < n {
+        return None;
+    }
+    let inactive = band as u32 + 1;
+    let mut column: Vec<u32> = (0..=n as u32).map(|i| i.min(inactive)).collect();
+    let mut last_active = n.min(band);
+    let mut best = (column[n] <= band as u32).then_some((column[n], 0));
+    for (j, &refr_base) in reference.iter().enumerate() {
+        let mut diag = column[0];
+        let bound = (last_active + 1).min(n);
+        for i in 1..=bound {
+            let mismatch = !read[i - 1].eq_ignore_ascii_case(&refr_base) as u32;
+            let dist = (diag + mismatch)
+                .min(column[i] + 1)
+                .min(column[i - 1] + 1)
+                .min(inactive);
+            diag = column[i];
+            column[i] = dist;
+        }
+        last_active = bound;
+        while 0 < last_active && inactive <= column[last_active] {
+            last_active -= 1;
+        }
+        if last_active == n && best.map(|(dist, _)| column[n] < dist).unwrap_or(true) {
+            best = Some((column[n], j + 1));
+        }
+        if let Some((0, _)) = best {
+            break;
+        }
+    }
+    let (dist, end) = best?;
+    let start = end.saturating_sub(n + band);
+    let (score, mut ops) = semi_global(read, &reference[start..end], 0, -1, -1);
+    debug_assert_eq!(score, -(dist as i32));
+    if let Some(Op::Deletion(_)) = ops.last() {
+        ops.pop();
+    }
+    match ops.first() {
+        Some(&Op::Deletion(l)) => Some((start + l, ops.split_off(1))),
+        _ => Some((start, ops)),
+    }
+}
+
+/// Global alignment with the substitution scores given by the `matrix`.
+/// See [`global`] for the details.
+pub fn global_with_matrix(
+    x1: &[u8],
+    x2: &[u8],
+    matrix: &ScoringMatrix,
+    gap: i32,
+) -> (i32, Vec<Op>) {
+    align_dp(x1, x2, |a, b| matrix.score(a, b), gap, false)
+}
+
+/// Semi-global alignment with the substitution scores given by the `matrix`.
+/// See [`semi_global`] for the details.
+pub fn semi_global_with_matrix(
+    x1: &[u8],
+    x2: &[u8],
+    matrix: &ScoringMatrix,
+    gap: i32,
+) -> (i32, Vec<Op>) {
+    align_dp(x1, x2, |a, b| matrix.score(a, b), gap, true)
+}
+
+/// Substitution scores between every pair of bytes.
+/// The letters are case-insensitive, i.e., `score(b'a', b'G') == score(b'A', b'G')`.
+#[derive(Clone, PartialEq, Eq)]
+pub struct ScoringMatrix {
+    scores: Vec<i32>,
+}
+
+impl std::fmt::Debug for ScoringMatrix {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_struct("ScoringMatrix").finish_non_exhaustive()
+    }
+}
+
+impl ScoringMatrix {
+    /// The matrix scoring `match_score` for identical letters and `mismatch` otherwise,
+    /// the same as the scores used by [`global`] and [`semi_global`].
+    pub fn new(match_score: i32, mismatch: i32) -> Self {
+        let mut scores = vec![mismatch; 256 * 256];
+        for a in 0..=255u8 {
+            for b in 0..=255u8 {
+                if a.eq_ignore_ascii_case(&b) {
+                    scores[a as usize * 256 + b as usize] = match_score;
+                }
+            }
+        }
+        Self { scores }
+    }
+    /// The nucleotide matrix scoring the transitions(A<->G, C<->T) and the transversions differently.
+    pub fn transition_transversion(match_score: i32, transition: i32, transversion: i32) -> Self {
+        let mut matrix = Self::new(match_score, transversion);
+        matrix.set(b'A', b'G', transition);
+        matrix.set(b'C', b'T', transition);
+        matrix
+    }
+    /// The BLOSUM62 matrix for amino acids. The letters not in the matrix are treated as `X`.
+    pub fn blosum62() -> Self {
+        let mut matrix = Self::new(0, 0);
+        let unknown = BLOSUM62_LETTERS.iter().position(|&x| x == b'X').unwrap();
+        let index = |x: u8| {
+            BLOSUM62_LETTERS
+                .iter()
+                .position(|y| y.eq_ignore_ascii_case(&x))
+                .unwrap_or(unknown)
+        };
+        for a in 0..=255u8 {
+            for b in 0..=255u8 {
+                matrix.scores[a as usize * 256 + b as usize] = BLOSUM62[index(a)][index(b)];
+            }
+        }
+        matrix
+    }
+    /// Set the score between `a` and `b`, both for (a,b) and (b,a) regardless of the case.
+    pub fn set(&mut self, a: u8, b: u8, score: i32) {
+        for &a in &[a.to_ascii_uppercase(), a.to_ascii_lowercase()] {
+            for &b in &[b.to_ascii_uppercase(), b.to_ascii_lowercase()] {
+                self.scores[a as usize * 256 + b as usize] = score;
+                self.scores[b as usize * 256 + a as usize] = score;
+            }
+        }
+    }
+    pub fn score(&self, a: u8, b: u8) -> i32 {
+        self.scores[a as usize * 256 + b as usize]
+    }
+}
+
+const BLOSUM62_LETTERS: &[u8; 24] = b"ARNDCQEGHILKMFPSTWYVBZX*";
+#[rustfmt::skip]
+const BLOSUM62: [[i32; 24]; 24] = [
+    [4, -1, -2, -2, 0, -1, -1, 0, -2, -1, -1, -1, -1, -2, -1, 1, 0, -3, -2, 0, -2, -1, 0, -4],
+    [-1, 5, 0, -2, -3, 1, 0, -2, 0, -3, -2, 2, -1, -3, -2, -1, -1, -3, -2, -3, -1, 0, -1, -4],
+    [-2, 0, 6, 1, -3, 0, 0, 0, 1, -3, -3, 0, -2, -3, -2, 1, 0, -4, -2, -3, 3, 0, -1, -4],
+    [-2, -2, 1, 6, -3, 0, 2, -1, -1, -3, -4, -1, -3, -3, -1, 0, -1, -4, -3, -3, 4, 1, -1, -4],
+    [0, -3, -3, -3, 9, -3, -4, -3, -3, -1, -1, -3, -1, -2, -3, -1, -1, -2, -2, -1, -3, -3, -2, -4],
+    [-1, 1, 0, 0, -3, 5, 2, -2, 0, -3, -2, 1, 0, -3, -1, 0, -1, -2, -1, -2, 0, 3, -1, -4],
+    [-1, 0, 0, 2, -4, 2, 5, -2, 0, -3, -3, 1, -2, -3, -1, 0, -1, -3, -2, -2, 1, 4, -1, -4],
+    [0, -2, 0, -1, -3, -2, -2, 6, -2, -4, -4, -2, -3, -3, -2, 0, -2, -2, -3, -3, -1, -2, -1, -4],
+    [-2, 0, 1, -1, -3, 0, 0, -2, 8, -3, -3, -1, -2, -1, -2, -1, -2, -2, 2, -3, 0, 0, -1, -4],
+    [-1, -3, -3, -3, -1, -3, -3, -4, -3, 4, 2, -3, 1, 0, -3, -2, -1, -3, -1, 3, -3, -3, -1, -4],
+    [-1, -2, -3, -4, -1, -2, -3, -4, -3, 2, 4, -2, 2, 0, -3, -2, -1, -2, -1, 1, -4, -3, -1, -4],
+    [-1, 2, 0, -1, -3, 1, 1, -2, -1, -3, -2, 5, -1, -3, -1, 0, -1, -3, -2, -2, 0, 1, -1, -4],
+    [-1, -1, -2, -3, -1, 0, -2, -3, -2, 1, 2, -1, 5, 0, -2, -1, -1, -1, -1, 1, -3, -1, -1, -4],
+    [-2, -3, -3, -3, -2, -3, -3, -3, -1, 0, 0, -3, 0, 6, -4, -2, -2, 1, 3, -1, -3, -3, -1, -4],
+    [-1, -2, -2, -1, -3, -1, -1, -2, -2, -3, -3, -1, -2, -4, 7, -1, -1, -4, -3, -2, -2, -1, -2, -4],
+    [1, -1, 1, 0, -1, 0, 0, 0, -1, -2, -2, 0, -1, -2, -1, 4, 1, -3, -2, -2, 0, 0, 0, -4],
+    [0, -1, 0, -1, -1, -1, -1, -2, -2, -1, -1, -1, -1, -2, -1, 1, 5, -2, -2, 0, -1, -1, 0, -4],
+    [-3, -3, -4, -4, -2, -2, -3, -2, -2, -3, -2, -3, -1, 1, -4, -3, -2, 11, 2, -3, -4, -3, -2, -4],
+    [-2, -2, -2, -3, -2, -1, -2, -3, 2, -1, -1, -2, -1, 3, -3, -2, -2, 2, 7, -1, -3, -2, -1, -4],
+    [0, -3, -3, -3, -1, -2, -2, -3, -3, 3, 1, -2, 1, -1, -2, -2, 0, -3, -1, 4, -3, -2, -1, -4],
+    [-2, -1, 3, 4, -3, 0, 1, -1, 0, -3, -4, 0, -3, -3, -2, 0, -1, -4, -3, -3, 4, 1, -1, -4],
+    [-1, 0, 0, 1, -3, 3, 4, -2, 0, -3, -3, 1, -1, -3, -1, 0, -1, -3, -2, -2, 1, 4, -1, -4],
+    [0, -1, -1, -1, -2, -1, -1, -1, -1, -1, -1, -1, -1, -1, -2, 0, 0, -2, -1, -1, -1, -1, -1, -4],
+    [-4, -4, -4, -4, -4, -4, -4, -4, -4, -4, -4, -4, -4, -4, -4, -4, -4, -4, -4, -4, -4, -4, -4, 1],
+];
+
+/// Multiple sequence alignment by the star alignment.
+/// The sequence with the smallest sum of the edit distances to the others is chosen as the center,
+/// and each sequence is aligned to the center by [`global`]. Then, the gaps are merged into
+/// the center("once a gap, always a gap").
+/// Return the gapped rows with `-`, in the same order as the input. All the rows have the same length.
+pub fn progressive_msa(seqs: &[&[u8]]) -> Vec<Vec<u8>> {
+    let center = match (0..seqs.len())
+        .min_by_key(|&i| seqs.iter().map(|s| edit_dist(seqs[i], s)).sum::<u32>())
+    {
+        Some(center) => seqs[center],
+        None => return vec![],
+    };
+    // For each sequence, the bases inserted before each position of the center,
+    // and whether the position of the center is aligned to a base(Some) or a gap(None).
+    let alignments: Vec<_> = seqs
+        .iter()
+        .map(|seq| {
+            let (_, ops) = global(seq, center, 1, -1, -1);
+            let mut insertions = vec![vec![]; center.len() + 1];
+            let mut columns = Vec::with_capacity(center.len());
+            let mut qpos = 0;
+            for op in ops {
+                match op {
+                    Op::Match(l) | Op::Mismatch(l) => {
+                        columns.extend(seq[qpos..qpos + l].iter().map(|&b| Some(b)));
+                        qpos += l;
+                    }
+                    Op::Insertion(l) => {
+                        insertions[columns.len()].extend_from_slice(&seq[qpos..qpos + l]);
+                        qpos += l;
+                    }
+                    Op::Deletion(l) => columns.extend(std::iter::repeat_n(None, l)),
+                    _ => unreachable!(),
+                }
+            }
+            (insertions, columns)
+        })
+        .collect();
+    let max_insertions: Vec<_> = (0..=center.len())
+        .map(|j| {
+            alignments
+                .iter()
+                .map(|(ins, _)| ins[j].len())
+                .max()
+                .unwrap_or(0)
+        })
+        .collect();
+    alignments
+        .iter()
+        .map(|(insertions, columns)| {
+            let mut row = vec![];
+            for (j, &max) in max_insertions.iter().enumerate() {
+                row.extend_from_slice(&insertions[j]);
+                row.extend(std::iter::repeat_n(b'-', max - insertions[j].len()));
+                if let Some(column) = columns.get(j) {
+                    row.push(column.unwrap_or(b'-'));
+                }
+            }
+            row
+        })
+        .collect()
+}
+
+/// Edit distance by the wavefront algorithm, taking O((|x1|+|x2|)s)-time in the worst case and
+/// O(s)-memory, where s is the distance.
+/// It is much faster than [`edit_dist`] when the two sequences are similar.
+pub fn edit_dist_wfa(x1: &[u8], x2: &[u8]) -> u32 {
+    let (n, m) = (x1.len() as i64, x2.len() as i64);
+    let mut wavefront = vec![extend(x1, x2, 0, 0)];
+    let mut score = 0;
+    // The diagonal k = j - i ends at k = m - n.
+    while wavefront_at(&wavefront, score, m - n) < m {
+        score += 1;
+        wavefront = next_wavefront(&wavefront, score, n, m)
+            .into_iter()
+            .enumerate()
+            .map(|(idx, j)| match j {
+                WFA_NONE => WFA_NONE,
+                j => extend(x1, x2, idx as i64 - score, j),
+            })
+            .collect();
+    }
+    score as u32
+}
+
+/// Global alignment by the wavefront algorithm with unit costs, the same as [`edit_dist_wfa`].
+/// Return the edit distance and the alignment in `=`/`X`/`I`/`D`, where `x1` is the query.
+/// As all the wavefronts are kept for the traceback, this takes O(s^2)-memory.
+pub fn wfa_align(x1: &[u8], x2: &[u8]) -> (u32, Vec<Op>) {
+    let (n, m) = (x1.len() as i64, x2.len() as i64);
+    let mut wavefronts = vec![vec![extend(x1, x2, 0, 0)]];
+    let mut score = 0;
+    while wavefront_at(&wavefronts[score as usize], score, m - n) < m {
+        score += 1;
+        let wavefront = next_wavefront(&wavefronts[score as usize - 1], score, n, m)
+            .into_iter()
+            .enumerate()
+            .map(|(idx, j)| match j {
+                WFA_NONE => WFA_NONE,
+                j => extend(x1, x2, idx as i64 - score, j),
+            })
+            .collect();
+        wavefronts.push(wavefront);
+    }
+    let mut ops = vec![];
+    let (mut k, mut j) = (m - n, m);
+    for s in (1..=score).rev() {
+        let prev = &wavefronts[s as usize - 1];
+        let (sub, ins, del) = wfa_sources(prev, s - 1, k, n, m);
+        let start = sub.max(ins).max(del);
+        push_op(&mut ops, Op::Match((j - start) as usize));
+        j = start;
+        if start == sub {
+            push_op(&mut ops, Op::Mismatch(1));
+            j -= 1;
+        } else if start == ins {
+            push_op(&mut ops, Op::Insertion(1));
+            k += 1;
+        } else {
+            push_op(&mut ops, Op::Deletion(1));
+            k -= 1;
+            j -= 1;
+        }
+    }
+    push_op(&mut ops, Op::Match(j as usize));
+    let ops = ops.into_iter().rev().fold(vec![], |mut ops, op| {
+        match op {
+            Op::Match(0) => {}
+            _ => push_op(&mut ops, op),
+        }
+        ops
+    });
+    (score as u32, ops)
+}
+
+const WFA_NONE: i64 = i64::MIN / 2;
+
+// The offset(the position on x2) of the diagonal `k` in the wavefront of the `score`.
+// The wavefront of the score s has the diagonals from -s to s.
+fn wavefront_at(wavefront: &[i64], score: i64, k: i64) -> i64 {
+    if k < -score || score < k {
+        WFA_NONE
+    } else {
+        wavefront[(k + score) as usize]
+    }
+}
+
+// The offsets reached from the wavefront of `score` to the diagonal `k` by a mismatch,
+// an insertion, and a deletion, respectively. Unreachable ones are `WFA_NONE`.
+fn wfa_sources(prev: &[i64], score: i64, k: i64, n: i64, m: i64) -> (i64, i64, i64) {
+    let valid = |j: i64| {
+        if j < 0 || m < j || j - k < 0 || n < j - k {
+            WFA_NONE
+        } else {
+            j
+        }
+    };
+    let sub = valid(wavefront_at(prev, score, k) + 1);
+    let ins = valid(wavefront_at(prev, score, k + 1));
+    let del = valid(wavefront_at(prev, score, k - 1) + 1);
+    (sub, ins, del)
+}
+
+// Compute the wavefront of the `score` before the extension.
+fn next_wavefront(prev: &[i64], score: i64, n: i64, m: i64) -> Vec<i64> {
+    (-score..=score)
+        .map(|k| {
+            let (sub, ins, del) = wfa_sources(prev, score - 1, k, n, m);
+            sub.max(ins).max(del).max(WFA_NONE)
+        })
+        .collect()
+}
+
+// Extend the offset `j` on the diagonal `k` along the matches.
+fn extend(x1: &[u8], x2: &[u8], k: i64, j: i64) -> i64 {
+    let (i, j) = ((j - k) as usize, j as usize);
+    let matches = x1[i..]
+        .iter()
+        .zip(x2[j..].iter())
+        .take_while(|(a, b)| a == b)
+        .count();
+    (j + matches) as i64
+}
+
+/// Edit distance where a transposition of two adjacent characters also costs one.
+/// This is the restricted version(a.k.a. the optimal string alignment distance), where
+/// a substring is never edited after transposed. It keeps three rows of the DP.
+pub fn damerau_levenshtein(x1: &[u8], x2: &[u8]) -> u32 {
+    let mut prev2: Vec<u32> = vec![0; x2.len() + 1];
+    let mut prev: Vec<u32> = (0..=x2.len() as u32).collect();
+    let mut row = vec![0; x2.len() + 1];
+    for (i, &x1_b) in x1.iter().enumerate() {
+        row[0] = i as u32 + 1;
+        for (j, &x2_b) in x2.iter().enumerate() {
+            let m = (x1_b != x2_b) as u32;
+            let mut dist = (prev[j + 1] + 1).min(row[j] + 1).min(prev[j] + m);
+            if 0 < i && 0 < j && x1_b == x2[j - 1] && x1[i - 1] == x2_b {
+                dist = dist.min(prev2[j - 1] + 1);
+            }
+            row[j + 1] = dist;
+        }
+        std::mem::swap(&mut prev2, &mut prev);
+        std::mem::swap(&mut prev, &mut row);
+    }
+    prev[x2.len()]
+}
+
+/// Return the identity of the alignment, `(L - nm) / L`, where `L` is the number of the alignment columns
+/// (`M`/`=`/`X`/`I`/`D`) in the CIGAR and `nm` is the edit distance, such as the NM tag.
+/// Clips, skips, and paddings are not columns. If there is no column, return 0.
+/// This is the definition used by [`crate::sam::Record::identity`].
+pub fn cigar_identity(ops: &[Op], nm: usize) -> f64 {
+    let columns: usize = ops
+        .iter()
+        .map(|op| match *op {
+            Op::Align(l) | Op::Match(l) | Op::Mismatch(l) | Op::Insertion(l) | Op::Deletion(l) => l,
+            _ => 0,
+        })
+        .sum();
+    if columns == 0 {
+        0.
+    } else {
+        (columns as f64 - nm as f64) / columns as f64
+    }
+}
+
+/// Return the gap-compressed identity of the alignment, `1 - (mismatches + G) / (M + G)`, where `M` is the number of
+/// the `M`/`=`/`X` columns, `G` is the number of the gaps(a run of `I` or `D` is one gap regardless of its length),
+/// and `mismatches` is the number of the mismatched bases. One minus this value is the `de` tag of minimap2.
+/// Unlike [`cigar_identity`], long indels such as structural variants barely lower the identity. If there is no column, return 0.
+pub fn gap_compressed_identity(ops: &[Op], mismatches: usize) -> f64 {
+    let (mut columns, mut gaps) = (0, 0);
+    let mut prev = None;
+    for op in ops.iter() {
+        match *op {
+            Op::Align(l) | Op::Match(l) | Op::Mismatch(l) => columns += l,
+            Op::Insertion(l) | Op::Deletion(l) if 0 < l => {
+                let same_gap = matches!(
+                    (prev, op),
+                    (Some(Op::Insertion(_)), Op::Insertion(_))
+                        | (Some(Op::Deletion(_)), Op::Deletion(_))
+                );
+                if !same_gap {
+                    gaps += 1;
+                }
+            }
+            _ => {}
+        }
+        prev = Some(*op);
+    }
+    if columns + gaps == 0 {
+        0.
+    } else {
+        1. - (mismatches + gaps) as f64 / (columns + gaps) as f64
     }
+}
+
+/// Return the length of the longest common subsequence.
+/// As [`edit_dist`], the DP keeps only a single row along the shorter sequence.
+pub fn lcs_length(x1: &[u8], x2: &[u8]) -> usize {
+    let (long, short) = if x1.len() < x2.len() {
+        (x2, x1)
+    } else {
+        (x1, x2)
+    };
+    let mut row = vec![0; short.len() + 1];
+    for l_b in long.iter() {
+        let mut diag = 0;
+        for (j, s_b) in short.iter().enumerate() {
+            let up = row[j + 1];
+            row[j + 1] = if l_b == s_b { diag + 1 } else { up.max(row[j]) };
+            diag = up;
+        }
+    }
+    row[short.len()]
+}
+
+/// Return one of the longest common subsequences.
+/// This takes O(|x1||x2|) memory. Use [`lcs_length`] if only the length is needed.
+pub fn lcs(x1: &[u8], x2: &[u8]) -> Vec<u8> {
+    let mut dp = vec![vec![0; x2.len() + 1]; x1.len() + 1];
     for (i, x1_b) in x1.iter().enumerate() {
         for (j, x2_b) in x2.iter().enumerate() {
-            let m = (x1_b != x2_b) as u32;
-            dp[i + 1][j + 1] = (dp[i][j + 1] + 1).min(dp[i + 1][j] + 1).min(dp[i][j] + m);
+            dp[i + 1][j + 1] = if x1_b == x2_b {
+                dp[i][j] + 1
+            } else {
+                dp[i][j + 1].max(dp[i + 1][j])
+            };
+        }
+    }
+    let (mut i, mut j) = (x1.len(), x2.len());
+    let mut result = Vec::with_capacity(dp[i][j]);
+    while 0 < i && 0 < j {
+        if x1[i - 1] == x2[j - 1] {
+            result.push(x1[i - 1]);
+            i -= 1;
+            j -= 1;
+        } else if dp[i - 1][j] >= dp[i][j - 1] {
+            i -= 1;
+        } else {
+            j -= 1;
         }
     }
-    dp[x1.len()][x2.len()]
+    result.reverse();
+    result
+}
+
+// Fill the DP table and trace back the optimal alignment.
+// If `free_ends` is true, the gaps at the start and the end of `x2` are not penalized.
+fn align_dp<F: Fn(u8, u8) -> i32>(
+    x1: &[u8],
+    x2: &[u8],
+    score: F,
+    gap: i32,
+    free_ends: bool,
+) -> (i32, Vec<Op>) {
+    let (n, m) = (x1.len(), x2.len());
+    let mut dp = vec![vec![0; m + 1]; n + 1];
+    for (i, row) in dp.iter_mut().enumerate() {
+        row[0] = i as i32 * gap;
+    }
+    if !free_ends {
+        for (j, cell) in dp[0].iter_mut().enumerate() {
+            *cell = j as i32 * gap;
+        }
+    }
+    for (i, &x1_b) in x1.iter().enumerate() {
+        for (j, &x2_b) in x2.iter().enumerate() {
+            let diag = dp[i][j] + score(x1_b, x2_b);
+            let ins = dp[i][j + 1] + gap;
+            let del = dp[i + 1][j] + gap;
+            dp[i + 1][j + 1] = diag.max(ins).max(del);
+        }
+    }
+    let end = match free_ends {
+        true => (0..=m).rev().max_by_key(|&j| dp[n][j]).unwrap(),
+        false => m,
+    };
+    let mut ops = vec![Op::Deletion(m - end)];
+    let (mut i, mut j) = (n, end);
+    while 0 < i || 0 < j {
+        if i == 0 {
+            ops.push(Op::Deletion(j));
+            j = 0;
+        } else if 0 < j && dp[i][j] == dp[i - 1][j - 1] + score(x1[i - 1], x2[j - 1]) {
+            match x1[i - 1].eq_ignore_ascii_case(&x2[j - 1]) {
+                true => ops.push(Op::Match(1)),
+                false => ops.push(Op::Mismatch(1)),
+            }
+            i -= 1;
+            j -= 1;
+        } else if dp[i][j] == dp[i - 1][j] + gap {
+            ops.push(Op::Insertion(1));
+            i -= 1;
+        } else {
+            ops.push(Op::Deletion(1));
+            j -= 1;
+        }
+    }
+    let ops = ops.into_iter().rev().fold(vec![], |mut ops, op| {
+        match op {
+            Op::Deletion(0) => {}
+            _ => push_op(&mut ops, op),
+        }
+        ops
+    });
+    (dp[n][end], ops)
+}
+
+// Compute the edit distance by using `row` as the DP buffer.
+fn edit_dist_with(row: &mut Vec<u32>, x1: &[u8], x2: &[u8]) -> u32 {
+    let (x1, x2) = strip_common_affix(x1, x2);
+    let (long, short) = if x1.len() < x2.len() {
+        (x2, x1)
+    } else {
+        (x1, x2)
+    };
+    row.clear();
+    row.extend(0..=short.len() as u32);
+    for (i, l_b) in long.iter().enumerate() {
+        let mut diag = row[0];
+        row[0] = i as u32 + 1;
+        for (j, s_b) in short.iter().enumerate() {
+            let up = row[j + 1];
+            let m = (l_b != s_b) as u32;
+            row[j + 1] = (up + 1).min(row[j] + 1).min(diag + m);
+            diag = up;
+        }
+    }
+    row[short.len()]
+}
+
+// Remove the common prefix and suffix, which never change the edit distance.
+fn strip_common_affix<'a>(x1: &'a [u8], x2: &'a [u8]) -> (&'a [u8], &'a [u8]) {
+    let prefix = x1.iter().zip(x2.iter()).take_while(|(a, b)| a == b).count();
+    let (x1, x2) = (&x1[prefix..], &x2[prefix..]);
+    let suffix = x1
+        .iter()
+        .rev()
+        .zip(x2.iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+    (&x1[..x1.len() - suffix], &x2[..x2.len() - suffix])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn edit_dist_test() {
+        assert_eq!(edit_dist(b"", b""), 0);
+        assert_eq!(edit_dist(b"ACGT", b""), 4);
+        assert_eq!(edit_dist(b"", b"ACGT"), 4);
+        assert_eq!(edit_dist(b"kitten", b"sitting"), 3);
+        assert_eq!(edit_dist(b"ACGTACGT", b"ACGACGT"), 1);
+        assert_eq!(edit_dist(b"AAAA", b"TTTT"), 4);
+    }
+    #[test]
+    fn gap_compressed_identity_test() {
+        use Op::*;
+        // 50M 20D 50M with 2 mismatches: the deletion is a single difference.
+        let ops = vec![SoftClip(5), Align(50), Deletion(20), Align(50)];
+        assert_eq!(gap_compressed_identity(&ops, 2), 1. - 3. / 101.);
+        assert_eq!(cigar_identity(&ops, 2 + 20), 98. / 120.);
+        let ops = crate::sam::parse_cigar_string("10=1X10=2I1D10=3I");
+        assert_eq!(gap_compressed_identity(&ops, 1), 1. - 4. / 34.);
+        let ops = vec![Align(10), Insertion(1), Insertion(2), Align(10)];
+        assert_eq!(gap_compressed_identity(&ops, 0), 1. - 1. / 21.);
+        assert_eq!(gap_compressed_identity(&[Align(10)], 0), 1.);
+        assert_eq!(gap_compressed_identity(&[HardClip(10)], 0), 0.);
+    }
+    #[test]
+    fn edit_dist_matrix_test() {
+        let dp = edit_dist_matrix(b"kitten", b"sitting");
+        assert_eq!(dp.len(), 7);
+        assert!(dp.iter().all(|row| row.len() == 8));
+        assert_eq!(dp[0][0], 0);
+        assert_eq!(dp[6][0], 6);
+        assert_eq!(dp[0][7], 7);
+        assert_eq!(dp[6][7], 3);
+        assert_eq!(dp[1][1], 1);
+        assert_eq!(dp[3][4], 2);
+        let mut state = 314_159;
+        for _ in 0..50 {
+            let x1: Vec<u8> = (0..xorshift(&mut state) % 30)
+                .map(|_| b"ACGT"[xorshift(&mut state) as usize % 4])
+                .collect();
+            let x2 = mutate(&x1, 5, &mut state);
+            let dp = edit_dist_matrix(&x1, &x2);
+            assert_eq!(dp[x1.len()][x2.len()], edit_dist(&x1, &x2));
+        }
+        assert_eq!(edit_dist_matrix(b"", b""), vec![vec![0]]);
+    }
+    #[test]
+    fn distance_matrix_test() {
+        let seqs: Vec<&[u8]> = vec![b"kitten", b"sitting", b"mitten"];
+        let matrix = distance_matrix(&seqs);
+        assert_eq!(matrix, vec![vec![0, 3, 1], vec![3, 0, 3], vec![1, 3, 0]]);
+        for i in 0..seqs.len() {
+            assert_eq!(matrix[i][i], 0);
+            for j in 0..seqs.len() {
+                assert_eq!(matrix[i][j], matrix[j][i]);
+                assert_eq!(matrix[i][j], edit_dist(seqs[i], seqs[j]));
+            }
+        }
+        assert!(distance_matrix(&[]).is_empty());
+    }
+    #[test]
+    fn edit_dist_large_similar() {
+        // A full DP matrix for these would take hundreds of gigabytes.
+        let len = 200_000;
+        let x1: Vec<u8> = (0..len).map(|i| b"ACGT"[(i * 7 + i / 3) % 4]).collect();
+        let mut x2 = x1.clone();
+        x2[len / 2] = if x2[len / 2] == b'A' { b'C' } else { b'A' };
+        x2.insert(len / 2 + 10, b'T');
+        assert_eq!(edit_dist(&x1, &x2), 2);
+    }
+    // Xorshift PRNG, to avoid depending on `rand` only for the tests.
+    fn xorshift(state: &mut u64) -> u64 {
+        *state ^= *state << 13;
+        *state ^= *state >> 7;
+        *state ^= *state << 17;
+        *state
+    }
+    fn mutate(seq: &[u8], num_edits: usize, state: &mut u64) -> Vec<u8> {
+        let mut seq = seq.to_vec();
+        for _ in 0..num_edits {
+            let pos = xorshift(state) as usize % (seq.len() + 1);
+            let base = b"ACGT"[xorshift(state) as usize % 4];
+            match xorshift(state) % 3 {
+                0 if pos < seq.len() => seq[pos] = base,
+                1 if pos < seq.len() => {
+                    seq.remove(pos);
+                }
+                _ => seq.insert(pos, base),
+            }
+        }
+        seq
+    }
+    fn apply_ops(x1: &[u8], x2: &[u8], ops: &[Op]) -> (Vec<u8>, Vec<u8>, u32) {
+        let (mut i, mut j, mut cost) = (0, 0, 0);
+        let (mut y1, mut y2) = (vec![], vec![]);
+        for op in ops {
+            match *op {
+                Op::Match(l) => {
+                    assert_eq!(x1[i..i + l], x2[j..j + l]);
+                    y1.extend_from_slice(&x1[i..i + l]);
+                    y2.extend_from_slice(&x2[j..j + l]);
+                    i += l;
+                    j += l;
+                }
+                Op::Mismatch(l) => {
+                    y1.extend_from_slice(&x1[i..i + l]);
+                    y2.extend_from_slice(&x2[j..j + l]);
+                    i += l;
+                    j += l;
+                    cost += l as u32;
+                }
+                Op::Insertion(l) => {
+                    y1.extend_from_slice(&x1[i..i + l]);
+                    i += l;
+                    cost += l as u32;
+                }
+                Op::Deletion(l) => {
+                    y2.extend_from_slice(&x2[j..j + l]);
+                    j += l;
+                    cost += l as u32;
+                }
+                _ => unreachable!(),
+            }
+        }
+        (y1, y2, cost)
+    }
+    #[test]
+    fn wfa_random_equivalence() {
+        let mut state = 2_463_534_242;
+        for _ in 0..500 {
+            let len = xorshift(&mut state) as usize % 60;
+            let x1: Vec<u8> = (0..len)
+                .map(|_| b"ACGT"[xorshift(&mut state) as usize % 4])
+                .collect();
+            let num_edits = xorshift(&mut state) as usize % 20;
+            let x2 = mutate(&x1, num_edits, &mut state);
+            let dist = edit_dist(&x1, &x2);
+            assert_eq!(edit_dist_wfa(&x1, &x2), dist);
+            let (score, ops) = wfa_align(&x1, &x2);
+            assert_eq!(score, dist);
+            let (y1, y2, cost) = apply_ops(&x1, &x2, &ops);
+            assert_eq!((y1, y2, cost), (x1.clone(), x2.clone(), dist));
+        }
+        assert_eq!(edit_dist_wfa(b"", b""), 0);
+        assert_eq!(edit_dist_wfa(b"", b"ACG"), 3);
+        assert_eq!(wfa_align(b"ACG", b""), (3, vec![Op::Insertion(3)]));
+    }
+    #[test]
+    fn wfa_long_similar() {
+        let mut state = 88_172_645_463_325_252;
+        let x1: Vec<u8> = (0..100_000)
+            .map(|_| b"ACGT"[xorshift(&mut state) as usize % 4])
+            .collect();
+        let x2 = mutate(&x1, 1_000, &mut state);
+        let dist = edit_dist_wfa(&x1, &x2);
+        assert!(0 < dist && dist <= 1_000);
+        let (score, ops) = wfa_align(&x1, &x2);
+        assert_eq!(score, dist);
+        assert_eq!(apply_ops(&x1, &x2, &ops).2, dist);
+    }
+    #[test]
+    fn damerau_levenshtein_test() {
+        assert_eq!(damerau_levenshtein(b"ACGT", b"AGCT"), 1);
+        assert_eq!(edit_dist(b"ACGT", b"AGCT"), 2);
+        assert_eq!(damerau_levenshtein(b"CA", b"ABC"), 3);
+        assert_eq!(damerau_levenshtein(b"", b"ACGT"), 4);
+        assert_eq!(damerau_levenshtein(b"ACGT", b""), 4);
+        assert_eq!(damerau_levenshtein(b"kitten", b"sitting"), 3);
+        assert_eq!(damerau_levenshtein(b"ACGTACGT", b"CAGTACTG"), 2);
+    }
+    #[test]
+    fn aligner_reuse() {
+        let seqs: Vec<&[u8]> = vec![
+            b"ACGTTGCA",
+            b"ACGTGCA",
+            b"",
+            b"TTTTTTTTTTTTT",
+            b"AGCTAGCTGA",
+        ];
+        let mut aligner = Aligner::new();
+        for x1 in seqs.iter() {
+            for x2 in seqs.iter() {
+                assert_eq!(aligner.edit_dist(x1, x2), edit_dist(x1, x2));
+            }
+        }
+    }
+    #[test]
+    fn cigar_from_gapped_test() {
+        use Op::*;
+        let query = b"ACG-TTAC--GTA";
+        let refr = b"ACGATT--CAGCA";
+        let ops = cigar_from_gapped(query, refr, false);
+        assert_eq!(
+            ops,
+            vec![
+                Align(3),
+                Deletion(1),
+                Align(2),
+                Insertion(2),
+                Deletion(2),
+                Align(3)
+            ]
+        );
+        let ops = cigar_from_gapped(query, refr, true);
+        assert_eq!(
+            ops,
+            vec![
+                Match(3),
+                Deletion(1),
+                Match(2),
+                Insertion(2),
+                Deletion(2),
+                Match(1),
+                Mismatch(1),
+                Match(1)
+            ]
+        );
+    }
+    #[test]
+    fn semi_global_test() {
+        use Op::*;
+        let query = b"ACGTTGCA";
+        let refr = b"TTTTTTACGTTGCATTTTTT";
+        let (score, ops) = semi_global(query, refr, 1, -1, -1);
+        assert_eq!(score, 8);
+        assert_eq!(ops, vec![Deletion(6), Match(8), Deletion(6)]);
+        let query = b"ACGTAGCAGGT";
+        let refr = b"CCCCCCCCACGTTGCAGGTCCC";
+        let (score, ops) = semi_global(query, refr, 1, -1, -1);
+        assert_eq!(score, 9);
+        assert_eq!(
+            ops,
+            vec![Deletion(8), Match(4), Mismatch(1), Match(6), Deletion(3)]
+        );
+        let (score, ops) = semi_global(b"ACGT", b"", 1, -1, -1);
+        assert_eq!(score, -4);
+        assert_eq!(ops, vec![Insertion(4)]);
+    }
+    #[test]
+    fn place_read_test() {
+        use Op::*;
+        let mut state = 1_234_567;
+        let reference: Vec<u8> = (0..500)
+            .map(|_| b"ACGT"[xorshift(&mut state) as usize % 4])
+            .collect();
+        let mut read = reference[200..260].to_vec();
+        read[20] = if read[20] == b'A' { b'C' } else { b'A' };
+        let ops = vec![Match(20), Mismatch(1), Match(39)];
+        assert_eq!(place_read(&read, &reference, 3), Some((200, ops)));
+        let read = mutate(&reference[100..150], 3, &mut state);
+        let (start, ops) = place_read(&read, &reference, 5).unwrap();
+        let end = start + apply_ops(&read, &reference[start..], &ops).1.len();
+        assert!(edit_dist(&read, &reference[start..end]) <= 3);
+        assert_eq!(place_read(&reference[50..80], &reference, 0).unwrap().0, 50);
+        let random: Vec<u8> = (0..60)
+            .map(|_| b"ACGT"[xorshift(&mut state) as usize % 4])
+            .collect();
+        assert_eq!(place_read(&random, &reference, 3), None);
+        assert_eq!(place_read(b"AC", b"", 2), Some((0, vec![Insertion(2)])));
+        assert_eq!(place_read(b"ACGT", b"A", 2), None);
+        // The scan stops at the first exact placement.
+        let repeat = [&reference[300..330], &reference[..], &reference[300..330]].concat();
+        assert_eq!(place_read(&reference[300..330], &repeat, 2).unwrap().0, 0);
+        for _ in 0..200 {
+            let start = xorshift(&mut state) as usize % 400;
+            let read = mutate(&reference[start..start + 40], 4, &mut state);
+            let (score, _) = semi_global(&read, &reference, 0, -1, -1);
+            let band = xorshift(&mut state) as usize % 6;
+            match place_read(&read, &reference, band) {
+                Some((start, ops)) => {
+                    let (_, _, cost) = apply_ops(&read, &reference[start..], &ops);
+                    assert_eq!(-(cost as i32), score);
+                }
+                None => assert!(-score > band as i32),
+            }
+        }
+    }
+    #[test]
+    fn cigar_identity_test() {
+        use Op::*;
+        // 10S 50M 2I 30M 3D 15M 5H: 100 columns, and the clips are ignored.
+        let ops = vec![
+            SoftClip(10),
+            Align(50),
+            Insertion(2),
+            Align(30),
+            Deletion(3),
+            Align(15),
+            HardClip(5),
+        ];
+        assert_eq!(cigar_identity(&ops, 8), 0.92);
+        assert_eq!(cigar_identity(&ops, 0), 1.);
+        let ops = crate::sam::parse_cigar_string("20=2X18=");
+        assert_eq!(cigar_identity(&ops, 2), 0.95);
+        assert_eq!(cigar_identity(&[SoftClip(10)], 0), 0.);
+        let line = "r\t0\tchr1\t1\t60\t10S50M2I30M3D15M5H\t*\t0\t0\t*\t*\tNM:i:8";
+        let record: crate::sam::Record = line.parse().unwrap();
+        assert_eq!(record.identity(), Some(0.92));
+    }
+    #[test]
+    fn global_test() {
+        let (score, ops) = global(b"ACGTACGT", b"ACGACGT", 1, -1, -1);
+        assert_eq!(score, 6);
+        assert_eq!(ops, vec![Op::Match(3), Op::Insertion(1), Op::Match(4)]);
+        let (score, ops) = global(b"ACGT", b"GGACGTCC", 1, -1, -1);
+        assert_eq!(score, 0);
+        assert_eq!(ops, vec![Op::Deletion(2), Op::Match(4), Op::Deletion(2)]);
+    }
+    #[test]
+    fn global_score_test() {
+        assert_eq!(global_score(b"ACGTACGT", b"ACGACGT", 1, -1, -1), 6);
+        assert_eq!(global_score(b"", b"ACG", 1, -1, -2), -6);
+        assert_eq!(global_score(b"AC", b"", 1, -1, -2), -4);
+        let mut state = 2_718;
+        for _ in 0..50 {
+            let x1: Vec<u8> = (0..xorshift(&mut state) % 40)
+                .map(|_| b"ACGT"[xorshift(&mut state) as usize % 4])
+                .collect();
+            let x2 = mutate(&x1, 8, &mut state);
+            let (score, _) = global(&x1, &x2, 2, -3, -2);
+            assert_eq!(global_score(&x1, &x2, 2, -3, -2), score);
+        }
+    }
+    #[test]
+    fn scoring_matrix_test() {
+        let uniform = ScoringMatrix::new(2, -2);
+        assert_eq!(uniform.score(b'a', b'A'), 2);
+        assert_eq!(uniform.score(b'A', b'G'), -2);
+        let (score, ops) = global_with_matrix(b"A", b"GC", &uniform, -2);
+        assert_eq!((score, ops.clone()), global(b"A", b"GC", 2, -2, -2));
+        assert_eq!(ops, vec![Op::Deletion(1), Op::Mismatch(1)]);
+        let ti_tv = ScoringMatrix::transition_transversion(2, 1, -2);
+        assert_eq!(ti_tv.score(b'g', b'A'), 1);
+        assert_eq!(ti_tv.score(b'A', b'C'), -2);
+        let (score, ops) = global_with_matrix(b"A", b"GC", &ti_tv, -2);
+        assert_eq!(score, -1);
+        assert_eq!(ops, vec![Op::Mismatch(1), Op::Deletion(1)]);
+        let blosum62 = ScoringMatrix::blosum62();
+        assert_eq!(blosum62.score(b'W', b'W'), 11);
+        assert_eq!(blosum62.score(b'a', b'S'), 1);
+        assert_eq!(blosum62.score(b'*', b'*'), 1);
+        assert_eq!(blosum62.score(b'J', b'A'), 0);
+        for &a in BLOSUM62_LETTERS.iter() {
+            for &b in BLOSUM62_LETTERS.iter() {
+                assert_eq!(blosum62.score(a, b), blosum62.score(b, a));
+            }
+        }
+        let (score, ops) = semi_global_with_matrix(b"HEAG", b"PAWHEAGE", &blosum62, -8);
+        assert_eq!(score, 8 + 5 + 4 + 6);
+        assert_eq!(ops, vec![Op::Deletion(3), Op::Match(4), Op::Deletion(1)]);
+    }
+    #[test]
+    fn progressive_msa_test() {
+        let seqs: Vec<&[u8]> = vec![b"ACGTACGTAC", b"ACGTTACGTAC", b"ACGACGTAC"];
+        let rows = progressive_msa(&seqs);
+        assert_eq!(rows.len(), 3);
+        assert!(rows.iter().all(|row| row.len() == rows[0].len()));
+        for (row, seq) in rows.iter().zip(seqs.iter()) {
+            let ungapped: Vec<_> = row.iter().filter(|&&b| b != b'-').copied().collect();
+            assert_eq!(&ungapped, seq);
+        }
+        assert_eq!(rows[1], b"ACGTTACGTAC".to_vec());
+        assert_eq!(rows[0].len(), 11);
+        assert!(progressive_msa(&[]).is_empty());
+        assert_eq!(progressive_msa(&[b"ACGT"]), vec![b"ACGT".to_vec()]);
+    }
+    #[test]
+    fn lcs_test() {
+        assert_eq!(lcs_length(b"AGGTAB", b"GXTXAYB"), 4);
+        assert_eq!(lcs(b"AGGTAB", b"GXTXAYB"), b"GTAB".to_vec());
+        assert_eq!(lcs_length(b"ACGT", b""), 0);
+        assert_eq!(lcs(b"ACGT", b"TTTT"), b"T".to_vec());
+        assert_eq!(
+            lcs_length(b"ACCGGTTA", b"CGTA"),
+            lcs(b"ACCGGTTA", b"CGTA").len()
+        );
+    }
+    #[test]
+    fn identity_test() {
+        assert_eq!(identity(b"ACGTACGT", b"ACGTACGT"), 1.);
+        assert_eq!(normalized_edit_distance(b"ACGTACGT", b"ACGTACGT"), 0.);
+        assert_eq!(identity(b"", b""), 1.);
+        assert!((identity(b"ACGTACGTAC", b"ACGTTCGTAC") - 0.9).abs() < 1e-10);
+        assert!((normalized_edit_distance(b"ACGTACGTAC", b"ACGTTCGTAC") - 0.1).abs() < 1e-10);
+    }
 }
@@ -0,0 +1,189 @@
+//! Translation from nucleotide sequences into amino acids.
+//! The genetic codes are the ones used by NCBI, specified by their table numbers.
+
+// The amino acids for each codon, in TCAG order, as in the NCBI's table.
+// In other words, the i-th amino acid is for the codon `b1b2b3` with i = 16*b1 + 4*b2 + b3,
+// where T=0, C=1, A=2, and G=3.
+const TABLES: [(u8, &str, &[u8; 64]); 12] = [
+    (
+        1,
+        "Standard",
+        b"FFLLSSSSYY**CC*WLLLLPPPPHHQQRRRRIIIMTTTTNNKKSSRRVVVVAAAADDEEGGGG",
+    ),
+    (
+        2,
+        "Vertebrate Mitochondrial",
+        b"FFLLSSSSYY**CCWWLLLLPPPPHHQQRRRRIIMMTTTTNNKKSS**VVVVAAAADDEEGGGG",
+    ),
+    (
+        3,
+        "Yeast Mitochondrial",
+        b"FFLLSSSSYY**CCWWTTTTPPPPHHQQRRRRIIMMTTTTNNKKSSRRVVVVAAAADDEEGGGG",
+    ),
+    (
+        4,
+        "Mold, Protozoan, and Coelenterate Mitochondrial and Mycoplasma/Spiroplasma",
+        b"FFLLSSSSYY**CCWWLLLLPPPPHHQQRRRRIIIMTTTTNNKKSSRRVVVVAAAADDEEGGGG",
+    ),
+    (
+        5,
+        "Invertebrate Mitochondrial",
+        b"FFLLSSSSYY**CCWWLLLLPPPPHHQQRRRRIIMMTTTTNNKKSSSSVVVVAAAADDEEGGGG",
+    ),
+    (
+        6,
+        "Ciliate, Dasycladacean and Hexamita Nuclear",
+        b"FFLLSSSSYYQQCC*WLLLLPPPPHHQQRRRRIIIMTTTTNNKKSSRRVVVVAAAADDEEGGGG",
+    ),
+    (
+        9,
+        "Echinoderm and Flatworm Mitochondrial",
+        b"FFLLSSSSYY**CCWWLLLLPPPPHHQQRRRRIIIMTTTTNNNKSSSSVVVVAAAADDEEGGGG",
+    ),
+    (
+        10,
+        "Euplotid Nuclear",
+        b"FFLLSSSSYY**CCCWLLLLPPPPHHQQRRRRIIIMTTTTNNKKSSRRVVVVAAAADDEEGGGG",
+    ),
+    (
+        11,
+        "Bacterial, Archaeal and Plant Plastid",
+        b"FFLLSSSSYY**CC*WLLLLPPPPHHQQRRRRIIIMTTTTNNKKSSRRVVVVAAAADDEEGGGG",
+    ),
+    (
+        12,
+        "Alternative Yeast Nuclear",
+        b"FFLLSSSSYY**CC*WLLLSPPPPHHQQRRRRIIIMTTTTNNKKSSRRVVVVAAAADDEEGGGG",
+    ),
+    (
+        13,
+        "Ascidian Mitochondrial",
+        b"FFLLSSSSYY**CCWWLLLLPPPPHHQQRRRRIIMMTTTTNNKKSSGGVVVVAAAADDEEGGGG",
+    ),
+    (
+        14,
+        "Alternative Flatworm Mitochondrial",
+        b"FFLLSSSSYYY*CCWWLLLLPPPPHHQQRRRRIIIMTTTTNNNKSSSSVVVVAAAADDEEGGGG",
+    ),
+];
+
+/// A genetic code.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CodonTable {
+    id: u8,
+    name: &'static str,
+    amino_acids: &'static [u8; 64],
+}
+
+impl CodonTable {
+    /// Return the genetic code with the NCBI table number, if it is supported.
+    /// Currently, the tables 1-6 and 9-14 are supported.
+    pub fn from_ncbi(id: u8) -> Option<Self> {
+        TABLES
+            .iter()
+            .find(|&&(table_id, _, _)| table_id == id)
+            .map(|&(id, name, amino_acids)| Self {
+                id,
+                name,
+                amino_acids,
+            })
+    }
+    /// The standard code (table 1).
+    pub fn standard() -> Self {
+        Self::from_ncbi(1).unwrap()
+    }
+    /// The NCBI table number.
+    pub fn id(&self) -> u8 {
+        self.id
+    }
+    pub fn name(&self) -> &str {
+        self.name
+    }
+    /// Translate a codon into an amino acid in one letter code. `*` is a stop codon.
+    /// The case is ignored and `U` is treated as `T`.
+    /// If the codon contains a base other than ACGTU, return `X`.
+    pub fn translate_codon(&self, codon: &[u8]) -> u8 {
+        let index = codon.iter().take(3).try_fold(0, |acc, &base| {
+            let base = match base {
+                b'T' | b't' | b'U' | b'u' => 0,
+                b'C' | b'c' => 1,
+                b'A' | b'a' => 2,
+                b'G' | b'g' => 3,
+                _ => return None,
+            };
+            Some(4 * acc + base)
+        });
+        match index {
+            Some(index) if codon.len() == 3 => self.amino_acids[index],
+            _ => b'X',
+        }
+    }
+    /// Return true if the codon is a stop codon in this table.
+    pub fn is_stop(&self, codon: &[u8]) -> bool {
+        self.translate_codon(codon) == b'*'
+    }
+}
+
+impl Default for CodonTable {
+    fn default() -> Self {
+        Self::standard()
+    }
+}
+
+/// Translate the sequence from the first base with the standard code.
+/// See [`translate_with`].
+pub fn translate(seq: &[u8]) -> Vec<u8> {
+    translate_with(seq, &CodonTable::standard(), 0)
+}
+
+/// Translate the sequence with the genetic code, starting from `seq[frame]`.
+/// The trailing bases not forming a complete codon are ignored.
+/// See [`CodonTable::translate_codon`] for the treatment of each codon.
+pub fn translate_with(seq: &[u8], table: &CodonTable, frame: usize) -> Vec<u8> {
+    seq.get(frame..)
+        .unwrap_or(&[])
+        .chunks_exact(3)
+        .map(|codon| table.translate_codon(codon))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn tables_are_complete() {
+        for &(id, _, amino_acids) in TABLES.iter() {
+            let table = CodonTable::from_ncbi(id).unwrap();
+            assert_eq!(table.id(), id);
+            assert!(amino_acids
+                .iter()
+                .all(|aa| aa.is_ascii_uppercase() || *aa == b'*'));
+        }
+        assert!(CodonTable::from_ncbi(7).is_none());
+    }
+    #[test]
+    fn translate_test() {
+        assert_eq!(translate(b"ATGGCCAAGTAA"), b"MAK*".to_vec());
+        assert_eq!(translate(b"augGCCaagtaaGG"), b"MAK*".to_vec());
+        assert_eq!(translate(b"ATGNNNTAA"), b"MX*".to_vec());
+        let table = CodonTable::standard();
+        assert_eq!(
+            translate_with(b"CATGGCCAAGTAA", &table, 1),
+            b"MAK*".to_vec()
+        );
+        assert!(translate_with(b"AT", &table, 3).is_empty());
+    }
+    #[test]
+    fn standard_vs_vertebrate_mito() {
+        let standard = CodonTable::from_ncbi(1).unwrap();
+        let mito = CodonTable::from_ncbi(2).unwrap();
+        assert_eq!(standard.translate_codon(b"TGA"), b'*');
+        assert_eq!(mito.translate_codon(b"TGA"), b'W');
+        assert!(standard.is_stop(b"TGA"));
+        assert!(!mito.is_stop(b"TGA"));
+        assert!(mito.is_stop(b"AGA"));
+        let seq = b"ATGTGAAAA";
+        assert_eq!(translate_with(seq, &standard, 0), b"M*K".to_vec());
+        assert_eq!(translate_with(seq, &mito, 0), b"MWK".to_vec());
+    }
+}
@@ -1,4 +1,5 @@
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::io;
 use std::io::{BufRead, BufReader};
 use std::io::{BufWriter, Write};
@@ -31,42 +32,89 @@ impl<R: io::Read> Reader<R> {
         if self.line.is_empty() {
             Ok(1)
         } else if !self.line.starts_with('>') {
-            Err(std::io::Error::from(std::io::ErrorKind::Other))
+            let message = format!("a record should start with '>': {}", self.line.trim_end());
+            Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                message,
+            ))
         } else {
             let mut header = self.line.split_whitespace();
             record.id = header.next().unwrap().trim_start_matches('>').to_string();
             record.desc = header.next().map(|e| e.to_string());
             while !self.line.is_empty() {
                 self.line.clear();
-                self.reader.read_line(&mut self.line).unwrap();
+                self.reader.read_line(&mut self.line)?;
                 if self.line.starts_with('>') {
                     break;
                 } else {
-                    record.seq.push_str(&self.line);
+                    record.seq.push_str(self.line.trim_end());
                 }
             }
             Ok(1)
         }
     }
     pub fn records(self) -> Records<R> {
-        Records { inner: self }
+        Records {
+            inner: self,
+            has_error_occured: false,
+        }
+    }
+    /// Iterate over the batches of `n` records, so that each batch can be processed in parallel
+    /// without loading the whole file. The last batch may have fewer records.
+    /// If an error occurs, the records read before it are yielded as a batch, followed by the error, and then the iterator ends.
+    /// A chunk size of zero is treated as one.
+    pub fn chunks(self, n: usize) -> impl Iterator<Item = std::io::Result<Vec<Record>>> {
+        let n = n.max(1);
+        let mut records = self.records();
+        let mut error = None;
+        std::iter::from_fn(move || {
+            if let Some(why) = error.take() {
+                return Some(Err(why));
+            }
+            let mut chunk = Vec::with_capacity(n);
+            for record in records.by_ref() {
+                match record {
+                    Ok(record) => chunk.push(record),
+                    Err(why) if chunk.is_empty() => return Some(Err(why)),
+                    Err(why) => {
+                        error = Some(why);
+                        break;
+                    }
+                }
+                if chunk.len() == n {
+                    break;
+                }
+            }
+            match chunk.is_empty() {
+                true => None,
+                false => Some(Ok(chunk)),
+            }
+        })
     }
 }
 
+/// Iterator over the records. After an error is yielded, the iterator ends.
 #[derive(Debug)]
 pub struct Records<R: io::Read> {
     inner: Reader<R>,
+    has_error_occured: bool,
 }
 
 impl<R: io::Read> Iterator for Records<R> {
     type Item = std::io::Result<Record>;
     fn next(&mut self) -> Option<Self::Item> {
+        if self.has_error_occured {
+            return None;
+        }
         let mut record = Record::default();
         let result = self.inner.read(&mut record);
         match result {
             Ok(_) if record.is_empty() => None,
             Ok(_) => Some(Ok(record)),
-            Err(why) => Some(Err(why)),
+            Err(why) => {
+                self.has_error_occured = true;
+                Some(Err(why))
+            }
         }
     }
 }
@@ -108,6 +156,178 @@ impl Record {
     pub fn desc(&self) -> Option<&String> {
         self.desc.as_ref()
     }
+    /// Convert the sequence into uppercase in place, e.g., to unmask soft-masked regions.
+    pub fn to_uppercase(&mut self) {
+        self.seq.make_ascii_uppercase();
+    }
+    /// Convert the sequence into lowercase in place.
+    pub fn to_lowercase(&mut self) {
+        self.seq.make_ascii_lowercase();
+    }
+    /// Return a copy of the record with the sequence in uppercase. The id and description are kept as they are.
+    pub fn uppercased(&self) -> Record {
+        let mut record = self.clone();
+        record.to_uppercase();
+        record
+    }
+    /// Return true if the two records have the same sequence, regardless of their ids.
+    pub fn seq_eq(&self, other: &Self) -> bool {
+        self.seq == other.seq
+    }
+    /// MD5 checksum of the uppercased sequence in hex, as used in VCF/refget and sequence dictionaries.
+    /// Whitespaces are removed before the computation.
+    pub fn seq_md5(&self) -> String {
+        let seq: Vec<u8> = self
+            .seq
+            .bytes()
+            .filter(|b| !b.is_ascii_whitespace())
+            .map(|b| b.to_ascii_uppercase())
+            .collect();
+        format!("{:x}", md5::compute(&seq))
+    }
+    /// Split the sequence into contigs at each run of at least `min_run` `N`s(in either case).
+    /// The fragments are named `id_1`, `id_2`, ..., and inherit the description.
+    /// The runs of `N`s at the both ends are removed, and shorter runs are kept in the contigs.
+    pub fn split_at_ns(&self, min_run: usize) -> Vec<Record> {
+        let min_run = min_run.max(1);
+        let seq = self.seq();
+        let mut fragments = vec![];
+        let (mut start, mut pos) = (0, 0);
+        while pos < seq.len() {
+            let run = seq[pos..]
+                .iter()
+                .take_while(|&&b| b == b'N' || b == b'n')
+                .count();
+            if min_run <= run {
+                if start < pos {
+                    fragments.push(&seq[start..pos]);
+                }
+                start = pos + run;
+            }
+            pos += run.max(1);
+        }
+        if start < seq.len() {
+            fragments.push(&seq[start..]);
+        }
+        fragments
+            .iter()
+            .enumerate()
+            .map(|(i, fragment)| {
+                Record::with_data(&format!("{}_{}", self.id, i + 1), &self.desc, fragment)
+            })
+            .collect()
+    }
+    /// Translate the sequence in the six frames with the standard code.
+    /// The first three are the frames 0, 1, and 2 of the forward strand,
+    /// and the last three are the frames 0, 1, and 2 of the reverse complement.
+    pub fn six_frame_translations(&self) -> [Vec<u8>; 6] {
+        use crate::codon::{translate_with, CodonTable};
+        let table = CodonTable::standard();
+        let forward = self.seq();
+        let reverse = crate::revcmp(forward);
+        [
+            translate_with(forward, &table, 0),
+            translate_with(forward, &table, 1),
+            translate_with(forward, &table, 2),
+            translate_with(&reverse, &table, 0),
+            translate_with(&reverse, &table, 1),
+            translate_with(&reverse, &table, 2),
+        ]
+    }
+    /// Find the open reading frames, from `ATG` to a stop codon, in the six frames with the standard code.
+    /// ORFs shorter than `min_len` codons(excluding the stop codon) are discarded.
+    /// If `include_partial` is true, ORFs running off the end of the sequence without a stop codon are also reported.
+    /// In each frame, ORFs do not overlap, i.e., an `ATG` inside an ORF does not start another ORF.
+    pub fn find_orfs(&self, min_len: usize, include_partial: bool) -> Vec<Orf> {
+        let len = self.len();
+        let frames = self.six_frame_translations();
+        let mut orfs = vec![];
+        for (i, protein) in frames.iter().enumerate() {
+            let (frame, strand) = match i {
+                0..=2 => (i, crate::Strand::Forward),
+                _ => (i - 3, crate::Strand::Reverse),
+            };
+            let mut codon = 0;
+            while let Some(start) = protein[codon..].iter().position(|&aa| aa == b'M') {
+                let start = codon + start;
+                let (end, is_complete) = match protein[start..].iter().position(|&aa| aa == b'*') {
+                    Some(stop) => (start + stop + 1, true),
+                    None => (protein.len(), false),
+                };
+                let codons = if is_complete {
+                    end - start - 1
+                } else {
+                    end - start
+                };
+                if min_len <= codons && (is_complete || include_partial) {
+                    // Coordinates on the strand, then converted into the forward strand.
+                    let (start, end) = (frame + 3 * start, frame + 3 * end);
+                    let (start, end) = match strand {
+                        crate::Strand::Forward => (start, end),
+                        crate::Strand::Reverse => (len - end, len - start),
+                    };
+                    orfs.push(Orf {
+                        frame,
+                        strand,
+                        start,
+                        end,
+                    });
+                }
+                codon = end;
+            }
+        }
+        orfs
+    }
+    /// GC content of the sequence. See [`crate::gc_content`].
+    pub fn gc_content(&self) -> f64 {
+        crate::gc_content(self.seq())
+    }
+    /// Base composition of the sequence. See [`crate::base_composition`].
+    pub fn base_composition(&self) -> [usize; 5] {
+        crate::base_composition(self.seq())
+    }
+    /// Guess the alphabet of the sequence from its letters(case-insensitive, non-letters are ignored).
+    /// It is a protein if it has a letter only used in amino acids, such as `E`, `F`, `I`, `L`, `P`, and `Q`,
+    /// or less than 90% of the letters are `A`/`C`/`G`/`T`/`U`/`N`. Otherwise, it is an RNA if it has `U` but no `T`,
+    /// and a DNA if not. An empty sequence is a DNA.
+    pub fn guess_alphabet(&self) -> Alphabet {
+        let letters = || self.seq().iter().filter(|b| b.is_ascii_alphabetic());
+        let has = |x: u8| letters().any(|b| b.eq_ignore_ascii_case(&x));
+        let total = letters().count();
+        let nucleotides = letters()
+            .filter(|b| b"ACGTUN".contains(&b.to_ascii_uppercase()))
+            .count();
+        if b"EFIJLOPQXZ".iter().any(|&b| has(b)) || 10 * nucleotides < 9 * total {
+            Alphabet::Protein
+        } else if has(b'U') && !has(b'T') {
+            Alphabet::Rna
+        } else {
+            Alphabet::Dna
+        }
+    }
+}
+
+/// The alphabet of a sequence. See [`Record::guess_alphabet`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Alphabet {
+    Dna,
+    Rna,
+    Protein,
+}
+
+/// Records are equal if they have the same id and sequence. The description is ignored.
+impl PartialEq for Record {
+    fn eq(&self, other: &Self) -> bool {
+        self.id == other.id && self.seq == other.seq
+    }
+}
+impl Eq for Record {}
+
+impl std::hash::Hash for Record {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        self.id.hash(state);
+        self.seq.hash(state);
+    }
 }
 
 impl std::convert::From<Record> for (String, Option<String>, String) {
@@ -126,6 +346,19 @@ impl std::fmt::Display for Record {
     }
 }
 
+/// An open reading frame found by [`Record::find_orfs`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Orf {
+    /// The frame (0, 1, or 2) on the strand.
+    pub frame: usize,
+    pub strand: crate::Strand,
+    /// The start position (0-based) on the forward strand.
+    pub start: usize,
+    /// The end position (exclusive) on the forward strand, including the stop codon if any.
+    /// Thus, `seq[start..end]` is the ORF, reverse complemented if the strand is reverse.
+    pub end: usize,
+}
+
 /// Fastest method to open and parse fasta file.
 pub fn parse_into_vec<P: AsRef<Path>>(file: P) -> std::io::Result<Vec<Record>> {
     let lines = std::fs::read_to_string(file)?;
@@ -154,7 +387,7 @@ pub fn parse_into_vec<P: AsRef<Path>>(file: P) -> std::io::Result<Vec<Record>> {
 }
 
 pub fn parse_into_vec_from<R: io::Read>(reader: R) -> std::io::Result<Vec<Record>> {
-    let mut lines = BufReader::new(reader).lines().filter_map(|e| e.ok());
+    let mut lines = BufReader::new(reader).lines().map_while(Result::ok);
     let mut result = Vec::with_capacity(10000);
     let mut line = lines.next().unwrap();
     loop {
@@ -179,15 +412,145 @@ pub fn parse_into_vec_from<R: io::Read>(reader: R) -> std::io::Result<Vec<Record
     }
 }
 
+/// Return the length of each record in the file, keyed by its id, without storing the sequences.
+/// As [`Reader`], the id is the header up to the first whitespace, such as a space or a tab.
+/// Whitespaces in the sequence lines are not counted. The lines before the first `>` are ignored.
+/// The result can be passed to [`crate::lasttab::try_from`] as the length map.
+pub fn sequence_lengths<P: AsRef<Path>>(file: P) -> std::io::Result<HashMap<String, usize>> {
+    let mut reader = BufReader::new(std::fs::File::open(file)?);
+    let mut lengths = HashMap::new();
+    let mut current: Option<(String, usize)> = None;
+    let mut line = vec![];
+    while reader.read_until(b'\n', &mut line)? > 0 {
+        if line.starts_with(b">") {
+            let header = String::from_utf8_lossy(&line[1..]);
+            let id = header.split_whitespace().next().unwrap_or("").to_owned();
+            if let Some((id, len)) = current.replace((id, 0)) {
+                lengths.insert(id, len);
+            }
+        } else if let Some((_, len)) = current.as_mut() {
+            *len += line.iter().filter(|b| !b.is_ascii_whitespace()).count();
+        }
+        line.clear();
+    }
+    if let Some((id, len)) = current {
+        lengths.insert(id, len);
+    }
+    Ok(lengths)
+}
+
+/// Iterate over the records in the buffer as `(id, seq)` without allocating a [`Record`] for each entry.
+/// Both the id and the sequence are slices into `buffer`. As a sequence may be wrapped into several lines,
+/// it is given as a [`SeqLines`], the raw block of the sequence lines. Use [`SeqLines::lines`] to scan it
+/// without copying, or [`SeqLines::to_vec`] to concatenate the lines.
+/// The lines before the first `>` are ignored.
+pub fn records_ref(buffer: &str) -> RecordsRef<'_> {
+    let rest = match buffer.find('>') {
+        Some(start) => &buffer[start..],
+        None => "",
+    };
+    RecordsRef { rest }
+}
+
+/// Iterator created by [`records_ref`].
+#[derive(Debug, Clone)]
+pub struct RecordsRef<'a> {
+    rest: &'a str,
+}
+
+impl<'a> Iterator for RecordsRef<'a> {
+    type Item = (&'a str, SeqLines<'a>);
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.rest.is_empty() {
+            return None;
+        }
+        let end = self.rest[1..].find("\n>").map(|i| i + 2);
+        let (record, rest) = self.rest.split_at(end.unwrap_or(self.rest.len()));
+        self.rest = rest;
+        let (header, block) = record[1..].split_once('\n').unwrap_or((&record[1..], ""));
+        let id = header.split_whitespace().next().unwrap_or("");
+        Some((id, SeqLines { block }))
+    }
+}
+
+/// The sequence lines of a record, borrowed from the buffer given to [`records_ref`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SeqLines<'a> {
+    block: &'a str,
+}
+
+impl<'a> SeqLines<'a> {
+    /// The raw block of the sequence lines, including the newlines.
+    pub fn as_str(&self) -> &'a str {
+        self.block
+    }
+    /// Iterate over the sequence lines without the trailing whitespaces. Empty lines are skipped.
+    pub fn lines(&self) -> impl Iterator<Item = &'a [u8]> {
+        self.block
+            .lines()
+            .map(|line| line.trim_end().as_bytes())
+            .filter(|line| !line.is_empty())
+    }
+    /// The length of the sequence.
+    pub fn len(&self) -> usize {
+        self.lines().map(|line| line.len()).sum()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.lines().next().is_none()
+    }
+    /// Concatenate the lines into a sequence. This allocates.
+    pub fn to_vec(&self) -> Vec<u8> {
+        let mut seq = Vec::with_capacity(self.block.len());
+        self.lines().for_each(|line| seq.extend_from_slice(line));
+        seq
+    }
+}
+
 #[derive(Debug)]
 pub struct Writer<W: Write> {
     writer: BufWriter<W>,
+    width: Option<usize>,
+}
+
+impl Writer<crate::OutputFile> {
+    /// Create a file to write the records, wrapping the sequences at `width` if specified.
+    /// If the path ends with `.gz`, the output is gzipped, which requires the `flate2` feature.
+    /// Call [`Writer::finish`] after the last record to see the errors on closing the file.
+    pub fn to_file<P: AsRef<Path>>(path: P, width: Option<usize>) -> std::io::Result<Self> {
+        crate::OutputFile::create(path).map(|file| Self::with_width(file, width))
+    }
+    /// Flush the buffer, finish the gzip stream if any, and return the file.
+    pub fn finish(self) -> std::io::Result<std::fs::File> {
+        self.into_inner()?.finish()
+    }
+}
+
+#[cfg(feature = "flate2")]
+impl Writer<flate2::write::GzEncoder<std::fs::File>> {
+    /// Create a gzipped file to write the records, wrapping the sequences at `width` if specified.
+    /// Call [`Writer::finish`] after the last record to write the gzip trailer and see the error.
+    /// Otherwise, the gzip stream is finished when the writer is dropped, and the error is lost.
+    pub fn to_gz_file<P: AsRef<Path>>(path: P, width: Option<usize>) -> std::io::Result<Self> {
+        let file = std::fs::File::create(path)?;
+        let encoder = flate2::write::GzEncoder::new(file, flate2::Compression::default());
+        Ok(Self::with_width(encoder, width))
+    }
+    /// Flush the buffer, finish the gzip stream, and return the file.
+    pub fn finish(self) -> std::io::Result<std::fs::File> {
+        self.into_inner()?.finish()
+    }
 }
 
 impl<W: Write> Writer<W> {
     pub fn new(w: W) -> Self {
+        Self::with_width(w, None)
+    }
+    /// Create a writer wrapping the sequences at every `width` bases.
+    /// If the `width` is `None`, each sequence is written in a single line.
+    pub fn with_width(w: W, width: Option<usize>) -> Self {
         Self {
             writer: BufWriter::new(w),
+            width: width.filter(|&width| 0 < width),
         }
     }
     pub fn write_record(&mut self, record: &Record) -> std::io::Result<()> {
@@ -198,8 +561,542 @@ impl<W: Write> Writer<W> {
             self.writer.write_all(desc.as_bytes())?;
         }
         self.writer.write_all(b"\n")?;
-        self.writer.write_all(record.seq.as_bytes())?;
-        self.writer.write_all(b"\n")?;
+        match self.width {
+            Some(width) => {
+                for line in record.seq().chunks(width) {
+                    self.writer.write_all(line)?;
+                    self.writer.write_all(b"\n")?;
+                }
+            }
+            None => {
+                self.writer.write_all(record.seq.as_bytes())?;
+                self.writer.write_all(b"\n")?;
+            }
+        }
+        Ok(())
+    }
+    /// Flush the buffer to the underlying writer. The records are buffered until the buffer is full,
+    /// this method is called, or the writer is dropped.
+    pub fn flush(&mut self) -> std::io::Result<()> {
         self.writer.flush()
     }
+    /// Flush the buffer and return the underlying writer.
+    pub fn into_inner(self) -> std::io::Result<W> {
+        self.writer.into_inner().map_err(|e| e.into_error())
+    }
+}
+
+/// Summary statistics of an assembly. See [`assembly_stats`].
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct AssemblyStats {
+    /// The number of sequences.
+    pub n_seqs: usize,
+    /// The total length of the sequences.
+    pub total_len: usize,
+    /// The length of the shortest sequence among the longest ones covering half of the total length.
+    pub n50: usize,
+    /// The number of the sequences needed to cover half of the total length.
+    pub l50: usize,
+    /// GC content of all the sequences. See [`crate::gc_content`].
+    pub gc: f64,
+    pub max_len: usize,
+    pub min_len: usize,
+}
+
+/// Extract the `(name, start, end, revcomp)` regions from the records, as `bedtools getfasta`.
+/// The coordinates are 0-based and half-open as in BED, and the outputs are named `name:start-end`.
+/// If `revcomp` is true, the region is reverse complemented.
+/// If a region runs off its record, it is clamped to the record when `clamp` is true,
+/// and otherwise an error of the `InvalidInput` kind is returned. So is it if the name is not in the records.
+pub fn extract_regions(
+    records: &HashMap<String, Record>,
+    regions: &[(String, usize, usize, bool)],
+    clamp: bool,
+) -> std::io::Result<Vec<Record>> {
+    let invalid = |message: String| std::io::Error::new(std::io::ErrorKind::InvalidInput, message);
+    regions
+        .iter()
+        .map(|(name, start, end, revcomp)| {
+            let record = records
+                .get(name)
+                .ok_or_else(|| invalid(format!("no such record: {}", name)))?;
+            let len = record.len();
+            let (start, end) = match clamp {
+                true => ((*start).min(len), (*end).min(len)),
+                false => (*start, *end),
+            };
+            if len < end || end < start {
+                let message = format!("invalid region {}:{}-{} (length {})", name, start, end, len);
+                return Err(invalid(message));
+            }
+            let id = format!("{}:{}-{}", name, start, end);
+            let seq = &record.seq()[start..end];
+            match revcomp {
+                true => Ok(Record::with_data(&id, &None, &crate::revcmp(seq))),
+                false => Ok(Record::with_data(&id, &None, seq)),
+            }
+        })
+        .collect()
+}
+
+/// Write the sequence dictionary of the records, as Picard's `CreateSequenceDictionary`.
+/// That is, a `@HD` line followed by a `@SQ` line with the name(`SN`), the length(`LN`),
+/// and the MD5 checksum(`M5`, see [`Record::seq_md5`]) for each record.
+pub fn write_dict<W: Write>(records: &[Record], w: &mut W) -> std::io::Result<()> {
+    writeln!(w, "@HD\tVN:1.6\tSO:unsorted")?;
+    for record in records {
+        let (id, len, md5) = (record.id(), record.len(), record.seq_md5());
+        writeln!(w, "@SQ\tSN:{}\tLN:{}\tM5:{}", id, len, md5)?;
+    }
+    Ok(())
+}
+
+/// Compute the summary statistics of the records. If the records are empty, every value is zero.
+pub fn assembly_stats(records: &[Record]) -> AssemblyStats {
+    let mut lengths: Vec<_> = records.iter().map(|r| r.len()).collect();
+    lengths.sort_unstable_by(|a, b| b.cmp(a));
+    let total_len: usize = lengths.iter().sum();
+    let (mut n50, mut l50, mut acc) = (0, 0, 0);
+    for &len in lengths.iter() {
+        acc += len;
+        l50 += 1;
+        if total_len <= 2 * acc {
+            n50 = len;
+            break;
+        }
+    }
+    let composition = records
+        .iter()
+        .map(|r| r.base_composition())
+        .fold([0; 5], |mut acc, comp| {
+            acc.iter_mut().zip(comp.iter()).for_each(|(x, y)| *x += y);
+            acc
+        });
+    let acgt: usize = composition[..4].iter().sum();
+    let gc = match acgt {
+        0 => 0.,
+        _ => (composition[1] + composition[2]) as f64 / acgt as f64,
+    };
+    AssemblyStats {
+        n_seqs: lengths.len(),
+        total_len,
+        n50,
+        l50,
+        gc,
+        max_len: lengths.first().copied().unwrap_or(0),
+        min_len: lengths.last().copied().unwrap_or(0),
+    }
+}
+
+/// The histogram of the sequence lengths in `bins` bins of the same width, from zero to the longest length.
+/// The i-th bin is `(bin_upper_bound, count)`, counting the sequences with the length in `(i*width, (i+1)*width]`,
+/// where `bin_upper_bound` is `(i+1)*width`. Empty sequences fall in the first bin.
+/// If the records are empty, so is the histogram. Panics if `bins` is zero.
+pub fn length_histogram(records: &[Record], bins: usize) -> Vec<(usize, usize)> {
+    assert!(bins != 0, "the number of bins should be positive");
+    let max_len = match records.iter().map(|r| r.len()).max() {
+        Some(len) => len,
+        None => return vec![],
+    };
+    let width = max_len.div_ceil(bins).max(1);
+    let mut histogram: Vec<_> = (1..=bins).map(|i| (i * width, 0)).collect();
+    for record in records {
+        let bin = record.len().saturating_sub(1) / width;
+        histogram[bin].1 += 1;
+    }
+    histogram
+}
+
+/// Join all the sequences with `spacer`, such as a run of `N`s, into a single record named `concatenated`.
+/// Return it with the `(name, start, end)` of each original record in the concatenated sequence,
+/// where the coordinates are 0-based and half-open, to translate the coordinates back.
+pub fn concatenate(records: &[Record], spacer: &[u8]) -> (Record, Vec<(String, usize, usize)>) {
+    let mut seq = vec![];
+    let mut offsets = Vec::with_capacity(records.len());
+    for (i, record) in records.iter().enumerate() {
+        if i != 0 {
+            seq.extend_from_slice(spacer);
+        }
+        let start = seq.len();
+        seq.extend_from_slice(record.seq());
+        offsets.push((record.id().to_string(), start, seq.len()));
+    }
+    (Record::with_data("concatenated", &None, &seq), offsets)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn assembly_stats_test() {
+        // Lengths: 2, 3, 4, 5, 6, 10. The total is 30, 10 + 6 = 16 >= 15.
+        let records: Vec<_> = [2, 3, 4, 5, 6, 10]
+            .iter()
+            .enumerate()
+            .map(|(i, &len)| {
+                let seq: Vec<_> = (0..len)
+                    .map(|j| if j % 2 == 0 { b'A' } else { b'G' })
+                    .collect();
+                Record::with_data(&format!("ctg{}", i), &None, &seq)
+            })
+            .collect();
+        let stats = assembly_stats(&records);
+        assert_eq!(stats.n_seqs, 6);
+        assert_eq!(stats.total_len, 30);
+        assert_eq!(stats.n50, 6);
+        assert_eq!(stats.l50, 2);
+        assert_eq!(stats.gc, 14. / 30.);
+        assert_eq!(stats.max_len, 10);
+        assert_eq!(stats.min_len, 2);
+        assert_eq!(assembly_stats(&[]), AssemblyStats::default());
+    }
+    #[test]
+    fn concatenate_test() {
+        let records = vec![
+            Record::with_data("ctg1", &None, b"ACGT"),
+            Record::with_data("ctg2", &Some("desc".to_string()), b"GG"),
+            Record::with_data("ctg3", &None, b"TTTAA"),
+        ];
+        let (joined, offsets) = concatenate(&records, b"NNN");
+        assert_eq!(joined.id(), "concatenated");
+        assert_eq!(joined.seq(), b"ACGTNNNGGNNNTTTAA");
+        let expected = vec![
+            ("ctg1".to_string(), 0, 4),
+            ("ctg2".to_string(), 7, 9),
+            ("ctg3".to_string(), 12, 17),
+        ];
+        assert_eq!(offsets, expected);
+        for ((_, start, end), record) in offsets.iter().zip(records.iter()) {
+            assert_eq!(&joined.seq()[*start..*end], record.seq());
+        }
+        for window in offsets.windows(2) {
+            assert_eq!(&joined.seq()[window[0].2..window[1].1], b"NNN");
+        }
+        let (joined, offsets) = concatenate(&records[..1], b"NNN");
+        assert_eq!(joined.seq(), b"ACGT");
+        assert_eq!(offsets, vec![("ctg1".to_string(), 0, 4)]);
+        let (joined, offsets) = concatenate(&[], b"NNN");
+        assert!(joined.seq().is_empty() && offsets.is_empty());
+    }
+    #[test]
+    fn length_histogram_test() {
+        let records: Vec<_> = [2, 3, 4, 5, 6, 10, 0]
+            .iter()
+            .map(|&len| Record::with_data("ctg", &None, &vec![b'A'; len]))
+            .collect();
+        let histogram = length_histogram(&records, 5);
+        assert_eq!(histogram, vec![(2, 2), (4, 2), (6, 2), (8, 0), (10, 1)]);
+        let histogram = length_histogram(&records, 3);
+        assert_eq!(histogram, vec![(4, 4), (8, 2), (12, 1)]);
+        let histogram = length_histogram(&records[6..], 2);
+        assert_eq!(histogram, vec![(1, 1), (2, 0)]);
+        assert!(length_histogram(&[], 4).is_empty());
+    }
+    #[test]
+    fn records_stop_after_error() {
+        let input: &[u8] = b">r1 desc\nACGT\nAC\n>r2\nAC\xffGT\n>r3\nTT\n";
+        let mut records = Reader::new(input).records();
+        let record = records.next().unwrap().unwrap();
+        assert_eq!(record.id(), "r1");
+        assert_eq!(record.seq(), b"ACGTAC");
+        assert!(records.next().unwrap().is_err());
+        assert!(records.next().is_none());
+        assert!(records.next().is_none());
+        let input: &[u8] = b"ACGT\n>r1\nACGT\n";
+        let mut records = Reader::new(input).records();
+        assert!(records.next().unwrap().is_err());
+        assert!(records.next().is_none());
+    }
+    #[test]
+    fn records_ref_test() {
+        let buffer = "; comment\n>r1 desc\nACGT\n>r2\nAC\nGT\r\nT\n>r3\nTTTT";
+        let records: Vec<_> = records_ref(buffer).collect();
+        let mut file = std::env::temp_dir();
+        file.push("bio_utils_records_ref_test.fa");
+        std::fs::write(&file, &buffer[10..]).unwrap();
+        let parsed = parse_into_vec(&file).unwrap();
+        std::fs::remove_file(&file).unwrap();
+        assert_eq!(records.len(), 3);
+        for ((id, seq), record) in records.iter().zip(parsed.iter()) {
+            assert_eq!(*id, record.id());
+            assert_eq!(seq.to_vec(), record.seq());
+            assert_eq!(seq.len(), record.len());
+        }
+        let range = buffer.as_bytes().as_ptr_range();
+        for (id, seq) in records.iter() {
+            assert!(range.contains(&id.as_ptr()));
+            assert!(range.contains(&seq.as_str().as_ptr()));
+            assert!(seq.lines().all(|line| range.contains(&line.as_ptr())));
+        }
+        let lines: Vec<_> = records[1].1.lines().collect();
+        assert_eq!(lines, vec![&b"AC"[..], b"GT", b"T"]);
+        assert_eq!(records[2].1.to_vec(), b"TTTT");
+        let (id, seq) = records_ref(">empty").next().unwrap();
+        assert_eq!(id, "empty");
+        assert!(seq.is_empty());
+        assert_eq!(records_ref("").count(), 0);
+    }
+    #[test]
+    fn sequence_lengths_test() {
+        let lengths = sequence_lengths("./testdata/test.fa").unwrap();
+        let records = parse_into_vec("./testdata/test.fa").unwrap();
+        assert_eq!(lengths.len(), records.len());
+        for record in records.iter() {
+            assert_eq!(lengths[record.id()], record.len());
+        }
+        assert_eq!(lengths["chr2"], 40);
+        let mut file = std::env::temp_dir();
+        file.push("bio_utils_sequence_lengths_test.fa");
+        std::fs::write(&file, "junk\n>r1 desc\r\nAC GT\r\n\nA\t\n>r2\n>r3\nTT").unwrap();
+        let lengths = sequence_lengths(&file).unwrap();
+        std::fs::remove_file(&file).unwrap();
+        assert_eq!(lengths.len(), 3);
+        assert_eq!((lengths["r1"], lengths["r2"], lengths["r3"]), (5, 0, 2));
+        // The id is delimited by any whitespace, as `Reader` does.
+        let input = ">r1\tdesc\nACGT\n>r2 \t desc\nAC\n";
+        std::fs::write(&file, input).unwrap();
+        let lengths = sequence_lengths(&file).unwrap();
+        std::fs::remove_file(&file).unwrap();
+        let records: Vec<_> = Reader::new(input.as_bytes())
+            .records()
+            .map(|r| r.unwrap())
+            .collect();
+        for record in records.iter() {
+            assert_eq!(lengths[record.id()], record.len());
+        }
+        assert_eq!((lengths["r1"], lengths["r2"]), (4, 2));
+    }
+    #[test]
+    fn extract_regions_test() {
+        let records: HashMap<_, _> = parse_into_vec("./testdata/test.fa")
+            .unwrap()
+            .into_iter()
+            .map(|record| (record.id().to_string(), record))
+            .collect();
+        let regions = vec![
+            ("chr1".to_string(), 2, 10, false),
+            ("chr2".to_string(), 8, 12, true),
+        ];
+        let extracted = extract_regions(&records, &regions, false).unwrap();
+        assert_eq!(extracted.len(), 2);
+        assert_eq!(extracted[0].id(), "chr1:2-10");
+        assert_eq!(extracted[0].seq(), b"GTAATGCC");
+        assert_eq!(extracted[1].id(), "chr2:8-12");
+        assert_eq!(extracted[1].seq(), b"NNTG");
+        let regions = vec![("plasmid".to_string(), 20, 30, false)];
+        let extracted = extract_regions(&records, &regions, true).unwrap();
+        assert_eq!(extracted[0].id(), "plasmid:20-23");
+        assert_eq!(extracted[0].seq(), b"CGC");
+        assert!(extract_regions(&records, &regions, false).is_err());
+        let regions = vec![("chr3".to_string(), 0, 1, false)];
+        assert!(extract_regions(&records, &regions, true).is_err());
+    }
+    #[test]
+    fn chunks_test() {
+        let input: String = (0..10).map(|i| format!(">r{}\nACGT\nAC\n", i)).collect();
+        let chunks: Vec<_> = Reader::new(input.as_bytes())
+            .chunks(4)
+            .map(|chunk| chunk.unwrap())
+            .collect();
+        let sizes: Vec<_> = chunks.iter().map(|chunk| chunk.len()).collect();
+        assert_eq!(sizes, vec![4, 4, 2]);
+        let ids: Vec<_> = chunks
+            .iter()
+            .flatten()
+            .map(|r| r.id().to_string())
+            .collect();
+        let expected: Vec<_> = (0..10).map(|i| format!("r{}", i)).collect();
+        assert_eq!(ids, expected);
+        assert!(chunks.iter().flatten().all(|r| r.seq() == b"ACGTAC"));
+        let total: usize = Reader::from_file("./testdata/test.fa")
+            .unwrap()
+            .chunks(2)
+            .map(|chunk| chunk.unwrap().len())
+            .sum();
+        assert_eq!(total, 3);
+        let input: &[u8] = b">r1\nA\n>r2\nC\n";
+        assert_eq!(Reader::new(input).chunks(5).count(), 1);
+        assert_eq!(Reader::new(&b""[..]).chunks(5).count(), 0);
+        assert_eq!(Reader::new(input).chunks(0).count(), 2);
+        let mut chunks = Reader::new(&b"ACGT\n>r1\nA\n"[..]).chunks(5);
+        assert!(chunks.next().unwrap().is_err());
+        assert!(chunks.next().is_none());
+    }
+    #[test]
+    fn guess_alphabet_test() {
+        let guess = |seq: &[u8]| Record::with_data("r", &None, seq).guess_alphabet();
+        assert_eq!(guess(b"ACGTNNacgtRYACGTACGTACGT"), Alphabet::Dna);
+        assert_eq!(guess(b"ACGUUGCAugcaNACG"), Alphabet::Rna);
+        assert_eq!(guess(b"MKVLAAGIVGLLLAQE*"), Alphabet::Protein);
+        assert_eq!(guess(b"MSTNKHRYVWMSTNKH"), Alphabet::Protein);
+        assert_eq!(guess(b"ACGTU"), Alphabet::Dna);
+        assert_eq!(guess(b""), Alphabet::Dna);
+    }
+    #[test]
+    fn write_dict_test() {
+        let records = vec![
+            Record::with_data("chr1", &Some("desc".to_string()), b"ACGTacgtNN"),
+            Record::with_data("chr2", &None, b"AC"),
+        ];
+        let mut dict = vec![];
+        write_dict(&records, &mut dict).unwrap();
+        let dict = String::from_utf8(dict).unwrap();
+        let lines: Vec<_> = dict.lines().collect();
+        assert_eq!(lines.len(), 3);
+        assert!(lines[0].starts_with("@HD\t"));
+        let md5 = format!("{:x}", md5::compute(b"ACGTACGTNN"));
+        assert_eq!(lines[1], format!("@SQ\tSN:chr1\tLN:10\tM5:{}", md5));
+        let fields: Vec<_> = lines[2].split('\t').collect();
+        assert_eq!(fields[..3], ["@SQ", "SN:chr2", "LN:2"]);
+        assert_eq!(fields[3], format!("M5:{}", records[1].seq_md5()));
+        let sam = crate::sam::Sam::from_reader(dict.as_bytes()).unwrap();
+        assert_eq!(sam.headers.len(), 3);
+    }
+    #[test]
+    fn case_normalization() {
+        let desc = Some("Mixed".to_string());
+        let mut record = Record::with_data("Chr1", &desc, b"ACgtnNacGT");
+        let upper = record.uppercased();
+        assert_eq!(upper.seq(), b"ACGTNNACGT");
+        assert_eq!(upper.id(), "Chr1");
+        assert_eq!(upper.desc(), desc.as_ref());
+        assert_eq!(record.seq(), b"ACgtnNacGT");
+        record.to_lowercase();
+        assert_eq!(record.seq(), b"acgtnnacgt");
+        record.to_uppercase();
+        assert_eq!(record, upper);
+    }
+    #[test]
+    fn writer_width() {
+        let records = vec![
+            Record::with_data("r1", &Some("desc".to_string()), b"ACGTACGTAC"),
+            Record::with_data("r2", &None, b"ACGT"),
+        ];
+        let mut writer = Writer::with_width(vec![], Some(4));
+        for record in records.iter() {
+            writer.write_record(record).unwrap();
+        }
+        let output = writer.into_inner().unwrap();
+        assert_eq!(output, b">r1 desc\nACGT\nACGT\nAC\n>r2\nACGT\n".to_vec());
+        let read: Vec<_> = Reader::new(output.as_slice())
+            .records()
+            .map(|r| r.unwrap())
+            .collect();
+        assert_eq!(read, records);
+    }
+    #[cfg(feature = "flate2")]
+    #[test]
+    fn gzipped_writer() {
+        let records = vec![
+            Record::with_data("r1", &Some("desc".to_string()), b"ACGTACGTAC"),
+            Record::with_data("r2", &None, b"ACGT"),
+        ];
+        let mut file = std::env::temp_dir();
+        file.push("bio_utils_gzipped_writer_test.fa.gz");
+        let mut writer = Writer::to_file(&file, Some(3)).unwrap();
+        for record in records.iter() {
+            writer.write_record(record).unwrap();
+        }
+        writer.finish().unwrap();
+        let gz = std::fs::File::open(&file)
+            .map(flate2::read::GzDecoder::new)
+            .unwrap();
+        let read: Vec<_> = Reader::new(gz).records().map(|r| r.unwrap()).collect();
+        assert_eq!(read, records);
+        let mut writer = Writer::to_gz_file(&file, None).unwrap();
+        for record in records.iter() {
+            writer.write_record(record).unwrap();
+        }
+        writer.finish().unwrap();
+        let gz = std::fs::File::open(&file)
+            .map(flate2::read::GzDecoder::new)
+            .unwrap();
+        let read: Vec<_> = Reader::new(gz).records().map(|r| r.unwrap()).collect();
+        std::fs::remove_file(&file).unwrap();
+        assert_eq!(read, records);
+        assert!(read
+            .iter()
+            .zip(records.iter())
+            .all(|(x, y)| x.desc() == y.desc()));
+    }
+    #[test]
+    fn record_equality() {
+        use std::collections::HashSet;
+        let desc = Some("sample".to_string());
+        let record1 = Record::with_data("seq1", &None, b"ACGT");
+        let record2 = Record::with_data("seq1", &desc, b"ACGT");
+        let record3 = Record::with_data("seq2", &desc, b"ACGT");
+        assert_eq!(record1, record2);
+        assert_ne!(record1, record3);
+        assert!(record1.seq_eq(&record3));
+        let set: HashSet<_> = vec![record1, record2, record3].into_iter().collect();
+        assert_eq!(set.len(), 2);
+    }
+    #[test]
+    fn split_at_ns_test() {
+        let record = Record::with_data("scaffold", &None, b"NNACGTNNNNNTTGNNCCNNNNNNGGAANN");
+        let contigs = record.split_at_ns(3);
+        assert_eq!(contigs.len(), 3);
+        assert_eq!(contigs[0].id(), "scaffold_1");
+        assert_eq!(contigs[0].seq(), b"NNACGT");
+        assert_eq!(contigs[1].id(), "scaffold_2");
+        assert_eq!(contigs[1].seq(), b"TTGNNCC");
+        assert_eq!(contigs[2].id(), "scaffold_3");
+        assert_eq!(contigs[2].seq(), b"GGAANN");
+        let contigs = record.split_at_ns(1);
+        assert_eq!(contigs.len(), 4);
+    }
+    #[test]
+    fn six_frame_translations_test() {
+        let record = Record::with_data("seq", &None, b"ATGGCCAAGTAACG");
+        let frames = record.six_frame_translations();
+        assert_eq!(frames[0], b"MAK*".to_vec());
+        assert_eq!(frames[1], b"WPSN".to_vec());
+        let revcmp = Record::with_data("rev", &None, &crate::revcmp(record.seq()));
+        let rev_frames = revcmp.six_frame_translations();
+        for i in 0..3 {
+            assert_eq!(frames[i + 3], rev_frames[i]);
+            assert_eq!(frames[i], rev_frames[i + 3]);
+        }
+    }
+    #[test]
+    fn find_orfs_test() {
+        use crate::Strand;
+        // An ORF of 5 codons + stop at [3,21) on the forward strand.
+        let seq = b"CCCATGAAACCCGGGTTTTAACCC";
+        let record = Record::with_data("seq", &None, seq);
+        let orfs = record.find_orfs(5, false);
+        let expected = Orf {
+            frame: 0,
+            strand: Strand::Forward,
+            start: 3,
+            end: 21,
+        };
+        assert_eq!(orfs, vec![expected]);
+        assert!(record.find_orfs(6, false).is_empty());
+        // The same ORF on the reverse strand.
+        let record = Record::with_data("rev", &None, &crate::revcmp(seq));
+        let orfs = record.find_orfs(5, false);
+        assert_eq!(orfs.len(), 1);
+        assert_eq!(orfs[0].strand, Strand::Reverse);
+        assert_eq!((orfs[0].start, orfs[0].end), (3, 21));
+        let orf = &record.seq()[orfs[0].start..orfs[0].end];
+        assert_eq!(
+            crate::codon::translate(&crate::revcmp(orf)),
+            b"MKPGF*".to_vec()
+        );
+        // No stop codon.
+        let record = Record::with_data("partial", &None, b"ATGAAACCCGGG");
+        assert!(record.find_orfs(3, false).is_empty());
+        let orfs = record.find_orfs(3, true);
+        assert_eq!(orfs.len(), 1);
+        assert_eq!((orfs[0].start, orfs[0].end), (0, 12));
+    }
+    #[test]
+    fn seq_md5_test() {
+        let record = Record::with_data("seq1", &None, b"acGT");
+        assert_eq!(record.seq_md5(), "f1f8f4bf413b16ad135722aa4591043e");
+        let record = Record::with_data("seq1", &None, b"");
+        assert_eq!(record.seq_md5(), "d41d8cd98f00b204e9800998ecf8427e");
+    }
 }
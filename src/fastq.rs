@@ -2,18 +2,49 @@
 use serde::{Deserialize, Serialize};
 use std::io;
 use std::io::{BufRead, BufReader};
+use std::io::{BufWriter, Write};
 use std::path::Path;
 #[derive(Debug)]
 pub struct Reader<R: io::Read> {
     reader: BufReader<R>,
     line: Vec<u8>,
+    strict: bool,
+    alphabet: Vec<u8>,
 }
 
+/// The sequence characters accepted in the strict mode by default.
+pub const DEFAULT_ALPHABET: &[u8] = b"ACGTNacgtn";
+
 impl Reader<std::fs::File> {
     pub fn from_file<P: AsRef<Path>>(file: P) -> std::io::Result<Self> {
-        let reader = std::fs::File::open(file).map(BufReader::new)?;
-        let line = Vec::new();
-        Ok(Self { reader, line })
+        std::fs::File::open(file).map(Self::new)
+    }
+}
+
+impl Reader<Box<dyn io::Read>> {
+    /// Open the file as [`Reader::from_file`], but gzipped files are detected by their magic number
+    /// and decompressed, which requires the `flate2` feature.
+    pub fn from_file_auto<P: AsRef<Path>>(file: P) -> std::io::Result<Self> {
+        use std::io::{Read, Seek};
+        let mut file = std::fs::File::open(file)?;
+        let mut magic = vec![];
+        (&mut file).take(2).read_to_end(&mut magic)?;
+        file.seek(std::io::SeekFrom::Start(0))?;
+        if magic != [0x1f, 0x8b] {
+            return Ok(Self::new(Box::new(file)));
+        }
+        #[cfg(feature = "flate2")]
+        {
+            Ok(Self::new(Box::new(flate2::read::MultiGzDecoder::new(file))))
+        }
+        #[cfg(not(feature = "flate2"))]
+        {
+            let message = "reading a gzipped file requires the `flate2` feature";
+            Err(std::io::Error::new(
+                std::io::ErrorKind::Unsupported,
+                message,
+            ))
+        }
     }
 }
 
@@ -21,7 +52,21 @@ impl<R: io::Read> Reader<R> {
     pub fn new(reader: R) -> Self {
         let line = Vec::new();
         let reader = BufReader::new(reader);
-        Self { reader, line }
+        Self {
+            reader,
+            line,
+            strict: false,
+            alphabet: DEFAULT_ALPHABET.to_vec(),
+        }
+    }
+    /// Validate each record on read: the separator line starts with `+`, the sequence and the quality have
+    /// the same length, and every base is in the alphabet. An invalid record is reported as an `InvalidData` error.
+    pub fn set_strict(&mut self, strict: bool) {
+        self.strict = strict;
+    }
+    /// Set the sequence characters accepted in the strict mode. By default, it is [`DEFAULT_ALPHABET`].
+    pub fn set_alphabet(&mut self, alphabet: &[u8]) {
+        self.alphabet = alphabet.to_vec();
     }
     pub fn read(&mut self, record: &mut Record) -> std::io::Result<usize> {
         // Note that the fastq file is four lines each.
@@ -31,38 +76,91 @@ impl<R: io::Read> Reader<R> {
         if self.line.is_empty() {
             return Ok(1);
         }
-        if !self.line[0] == b'@' {
+        if self.line[0] != b'@' {
             return Err(std::io::Error::from(std::io::ErrorKind::Other));
         }
-        self.line.pop().unwrap();
+        chomp(&mut self.line);
         record.id = String::from_utf8_lossy(&self.line[1..]).to_string();
         // Base
         self.line.clear();
         self.reader.read_until(b'\n', &mut self.line)?;
         assert!(record.seq.is_empty());
-        self.line.pop().unwrap();
+        chomp(&mut self.line);
         record.seq.extend_from_slice(&self.line);
         // Empty
         self.line.clear();
         self.reader.read_until(b'\n', &mut self.line)?;
+        if self.strict && !self.line.starts_with(b"+") {
+            let message = format!("{}: the separator line does not start with '+'", record.id);
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                message,
+            ));
+        }
         // Quality.
         self.line.clear();
         self.reader.read_until(b'\n', &mut self.line)?;
         assert!(record.qual.is_empty());
-        self.line.pop().unwrap();
+        chomp(&mut self.line);
         record.qual.extend_from_slice(&self.line);
+        if self.strict {
+            self.validate(record)?;
+        }
         Ok(1)
     }
+    fn validate(&self, record: &Record) -> std::io::Result<()> {
+        if record.seq.len() != record.qual.len() {
+            let message = format!(
+                "{}: the sequence has {} bases but the quality has {} characters",
+                record.id,
+                record.seq.len(),
+                record.qual.len()
+            );
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                message,
+            ));
+        }
+        if let Some(b) = record.seq.iter().find(|b| !self.alphabet.contains(b)) {
+            let message = format!("{}: invalid base '{}'", record.id, *b as char);
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                message,
+            ));
+        }
+        Ok(())
+    }
     pub fn records(self) -> Records<R> {
         Records { inner: self }
     }
 }
 
+/// Remove the trailing `\n`, and the preceding `\r` if any. The last line of a file may lack both.
+fn chomp(line: &mut Vec<u8>) {
+    if line.last() == Some(&b'\n') {
+        line.pop();
+        if line.last() == Some(&b'\r') {
+            line.pop();
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct Records<R: io::Read> {
     inner: Reader<R>,
 }
 
+impl<R: io::Read> Records<R> {
+    /// Keep only the reads with the entropy not less than `min`, dropping low-complexity reads
+    /// such as homopolymers. Errors are passed through. See [`Record::shannon_entropy`].
+    pub fn min_complexity(self, min: f64) -> impl Iterator<Item = std::io::Result<Record>> {
+        self.filter(move |record| match record {
+            Ok(record) => min <= record.shannon_entropy(),
+            Err(_) => true,
+        })
+    }
+}
+
 impl<R: io::Read> Iterator for Records<R> {
     type Item = std::io::Result<Record>;
     fn next(&mut self) -> Option<Self::Item> {
@@ -119,6 +217,104 @@ impl Record {
     pub fn quality(&self) -> &[u8] {
         &self.qual
     }
+    /// The read name without the mate suffix, so that the mates in paired-end files have the same name.
+    /// The description after the first whitespace, such as the Casava-style ` 1:N:0:ATCACG`, is removed,
+    /// and then the trailing `/1` or `/2` is stripped.
+    pub fn canonical_id(&self) -> &str {
+        let id = self.id.split_whitespace().next().unwrap_or("");
+        id.strip_suffix("/1")
+            .or_else(|| id.strip_suffix("/2"))
+            .unwrap_or(id)
+    }
+    /// The i7 and i5 index sequences in the Casava 1.8 description, such as `ATCACG` and `GATCGT` in ` 1:N:0:ATCACG+GATCGT`.
+    /// For a single-index read, the i5 index is empty.
+    /// If the description is not in the `read:filtered:control:index` form, return `None`.
+    pub fn indices(&self) -> Option<(&str, &str)> {
+        let block = self.id.split_whitespace().nth(1)?;
+        let mut fields = block.splitn(4, ':');
+        let read = fields.next()?;
+        let filtered = fields.next()?;
+        let control = fields.next()?;
+        let index = fields.next()?;
+        let is_number = |x: &str| !x.is_empty() && x.bytes().all(|b| b.is_ascii_digit());
+        if !is_number(read) || !matches!(filtered, "Y" | "N") || !is_number(control) {
+            return None;
+        }
+        match index.split_once('+') {
+            Some((i7, i5)) => Some((i7, i5)),
+            None if !index.is_empty() => Some((index, "")),
+            None => None,
+        }
+    }
+    /// Convert into a FASTA record, dropping the quality.
+    pub fn to_fasta(&self) -> crate::fasta::Record {
+        crate::fasta::Record::with_data(&self.id, &None, &self.seq)
+    }
+    /// Split off the first `len` bases, such as an inline barcode or UMI.
+    /// Return the record with the remaining bases and qualities, and the extracted bases.
+    /// If `len` is longer than the read, all the bases are extracted.
+    pub fn extract_prefix(&self, len: usize) -> (Record, Vec<u8>) {
+        let len = len.min(self.seq.len());
+        // The quality may be shorter than the sequence unless the reader is strict.
+        let qual = &self.qual[len.min(self.qual.len())..];
+        let trimmed = Record::with_data(&self.id, &self.seq[len..], qual);
+        (trimmed, self.seq[..len].to_vec())
+    }
+    /// Remove the homopolymer tail of `base`(case-insensitive), such as poly-A in RNA-seq or poly-G in two-color chemistry,
+    /// from the 3' end with the qualities. The tail may contain one mismatch per 8 bases, but it starts with `base`.
+    /// If the tail is shorter than `min_len`, nothing is trimmed.
+    pub fn trim_polyx(&self, base: u8, min_len: usize) -> Record {
+        let len = self.seq.len();
+        let mut tail_start = len;
+        let mut mismatches = 0;
+        for (i, b) in self.seq.iter().enumerate().rev() {
+            if b.eq_ignore_ascii_case(&base) {
+                tail_start = i;
+            } else {
+                mismatches += 1;
+                if (len - i) / 8 < mismatches {
+                    break;
+                }
+            }
+        }
+        if len - tail_start < min_len.max(1) {
+            return self.clone();
+        }
+        let qual = &self.qual[..tail_start.min(self.qual.len())];
+        Record::with_data(&self.id, &self.seq[..tail_start], qual)
+    }
+    /// Count the occurrences of each Phred score(Phred+33 encoding).
+    /// Scores larger than 63 are counted as 63.
+    pub fn quality_histogram(&self) -> [usize; 64] {
+        let mut histogram = [0; 64];
+        for &q in self.qual.iter() {
+            histogram[(q.saturating_sub(33) as usize).min(63)] += 1;
+        }
+        histogram
+    }
+    /// GC content of the sequence. See [`crate::gc_content`].
+    pub fn gc_content(&self) -> f64 {
+        crate::gc_content(self.seq())
+    }
+    /// The Shannon entropy of the base composition in bits, computed over `A`, `C`, `G`, and `T`(case-insensitive).
+    /// It ranges from 0 for a homopolymer to 2 for equal frequencies. If there is no such base, return 0.
+    /// Note that dinucleotide repeats such as `ATATAT` have the entropy 1.
+    pub fn shannon_entropy(&self) -> f64 {
+        let composition = self.base_composition();
+        let total: usize = composition[..4].iter().sum();
+        composition[..4]
+            .iter()
+            .filter(|&&count| 0 < count)
+            .map(|&count| {
+                let p = count as f64 / total as f64;
+                -p * p.log2()
+            })
+            .sum()
+    }
+    /// Base composition of the sequence. See [`crate::base_composition`].
+    pub fn base_composition(&self) -> [usize; 5] {
+        crate::base_composition(self.seq())
+    }
 }
 
 impl std::fmt::Display for Record {
@@ -142,3 +338,403 @@ pub fn parse_into_vec_from<R: io::Read>(reader: R) -> std::io::Result<Vec<Record
         .fuse()
         .collect())
 }
+
+/// Aggregate [`Record::quality_histogram`] over all the records in the file, which may be gzipped.
+/// See [`Reader::from_file_auto`].
+pub fn quality_histogram_from_file<P: AsRef<Path>>(file: P) -> std::io::Result<[usize; 64]> {
+    let mut histogram = [0; 64];
+    for record in Reader::from_file_auto(file)?.records() {
+        for (total, count) in histogram.iter_mut().zip(record?.quality_histogram().iter()) {
+            *total += count;
+        }
+    }
+    Ok(histogram)
+}
+
+/// Sample `n` reads uniformly at random in a single pass(reservoir sampling), keeping only `n` reads in memory.
+/// The same `seed` gives the same sample. If there are not more than `n` reads, all of them are returned.
+/// The sampled reads are not in the input order.
+pub fn reservoir_sample<R: io::Read>(
+    reader: R,
+    n: usize,
+    seed: u64,
+) -> std::io::Result<Vec<Record>> {
+    // Xorshift64, whose state should not be zero. The seed is scrambled, as small seeds give small numbers at first.
+    let mut state = (seed ^ 0x9E37_79B9_7F4A_7C15).max(1);
+    let mut xorshift = || {
+        state ^= state << 13;
+        state ^= state >> 7;
+        state ^= state << 17;
+        state
+    };
+    let mut sample = Vec::with_capacity(n);
+    for (i, record) in Reader::new(reader).records().enumerate() {
+        let record = record?;
+        if i < n {
+            sample.push(record);
+        } else {
+            let j = (xorshift() % (i as u64 + 1)) as usize;
+            if j < n {
+                sample[j] = record;
+            }
+        }
+    }
+    Ok(sample)
+}
+
+#[derive(Debug)]
+pub struct Writer<W: Write> {
+    writer: BufWriter<W>,
+}
+
+impl Writer<crate::OutputFile> {
+    /// Create a file to write the records.
+    /// If the path ends with `.gz`, the output is gzipped, which requires the `flate2` feature.
+    /// Call [`Writer::finish`] after the last record to see the errors on closing the file.
+    pub fn to_file<P: AsRef<Path>>(path: P) -> std::io::Result<Self> {
+        crate::OutputFile::create(path).map(Self::new)
+    }
+    /// Flush the buffer, finish the gzip stream if any, and return the file.
+    pub fn finish(self) -> std::io::Result<std::fs::File> {
+        self.into_inner()?.finish()
+    }
+}
+
+#[cfg(feature = "flate2")]
+impl Writer<flate2::write::GzEncoder<std::fs::File>> {
+    /// Create a gzipped file to write the records.
+    /// Call [`Writer::finish`] after the last record to write the gzip trailer and see the error.
+    /// Otherwise, the gzip stream is finished when the writer is dropped, and the error is lost.
+    pub fn to_gz_file<P: AsRef<Path>>(path: P) -> std::io::Result<Self> {
+        let file = std::fs::File::create(path)?;
+        let encoder = flate2::write::GzEncoder::new(file, flate2::Compression::default());
+        Ok(Self::new(encoder))
+    }
+    /// Flush the buffer, finish the gzip stream, and return the file.
+    pub fn finish(self) -> std::io::Result<std::fs::File> {
+        self.into_inner()?.finish()
+    }
+}
+
+impl<W: Write> Writer<W> {
+    pub fn new(w: W) -> Self {
+        Self {
+            writer: BufWriter::new(w),
+        }
+    }
+    pub fn write_record(&mut self, record: &Record) -> std::io::Result<()> {
+        writeln!(self.writer, "{}", record)
+    }
+    /// Flush the buffer and return the underlying writer.
+    pub fn into_inner(self) -> std::io::Result<W> {
+        self.writer.into_inner().map_err(|e| e.into_error())
+    }
+}
+
+/// Return the `(mean, min, max)` of the Phred scores(Phred+33 encoding) at each position of the reads.
+/// The length of the result is the length of the longest read, and each position is
+/// aggregated over the reads long enough to have it.
+pub fn per_position_quality(records: &[Record]) -> Vec<(f64, u8, u8)> {
+    // (sum, count, min, max) at each position.
+    let mut summary: Vec<(u64, u64, u8, u8)> = vec![];
+    for record in records {
+        if summary.len() < record.qual.len() {
+            summary.resize(record.qual.len(), (0, 0, u8::MAX, 0));
+        }
+        for (acc, &q) in summary.iter_mut().zip(record.qual.iter()) {
+            let q = q.saturating_sub(33);
+            acc.0 += q as u64;
+            acc.1 += 1;
+            acc.2 = acc.2.min(q);
+            acc.3 = acc.3.max(q);
+        }
+    }
+    summary
+        .into_iter()
+        .map(|(sum, count, min, max)| (sum as f64 / count as f64, min, max))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn reservoir_sample_test() {
+        let input: String = (0..100)
+            .map(|i| format!("@read{}\nACGT\n+\nIIII\n", i))
+            .collect();
+        let ids = |records: &[Record]| -> Vec<String> {
+            records.iter().map(|r| r.id().to_string()).collect()
+        };
+        let sample = reservoir_sample(input.as_bytes(), 10, 42).unwrap();
+        assert_eq!(sample.len(), 10);
+        let mut sorted = ids(&sample);
+        sorted.sort();
+        sorted.dedup();
+        assert_eq!(sorted.len(), 10);
+        let again = reservoir_sample(input.as_bytes(), 10, 42).unwrap();
+        assert_eq!(ids(&again), ids(&sample));
+        let other = reservoir_sample(input.as_bytes(), 10, 43).unwrap();
+        assert_ne!(ids(&other), ids(&sample));
+        // Each read is sampled with the probability 1/10.
+        let mut counts = [0; 100];
+        for seed in 0..1000 {
+            for record in reservoir_sample(input.as_bytes(), 10, seed).unwrap() {
+                counts[record.id()[4..].parse::<usize>().unwrap()] += 1;
+            }
+        }
+        assert!(counts.iter().all(|&c| 50 < c && c < 150), "{:?}", counts);
+        let all = reservoir_sample(input.as_bytes(), 200, 42).unwrap();
+        assert_eq!(all.len(), 100);
+        assert!(reservoir_sample(input.as_bytes(), 0, 42)
+            .unwrap()
+            .is_empty());
+    }
+    #[test]
+    fn no_trailing_newline_test() {
+        let input: &[u8] = b"@r1\nACGT\n+\nIIII\n@r2\nACG\n+\nII#";
+        let records: Vec<_> = Reader::new(input).records().map(|r| r.unwrap()).collect();
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[1].id(), "r2");
+        assert_eq!(records[1].seq(), b"ACG");
+        assert_eq!(records[1].quality(), b"II#");
+        let input: &[u8] = b"@r1\r\nACGT\r\n+\r\nIIII\r\n";
+        let records: Vec<_> = Reader::new(input).records().map(|r| r.unwrap()).collect();
+        assert_eq!(records[0].id(), "r1");
+        assert_eq!(records[0].seq(), b"ACGT");
+        assert_eq!(records[0].quality(), b"IIII");
+        let input: &[u8] = b"@r1\nACGT\n+\nIIII\nACGT\n";
+        let records: Vec<_> = Reader::new(input).records().collect();
+        assert!(records[1].is_err());
+    }
+    #[test]
+    fn strict_test() {
+        let input: &[u8] = b"@r1\nACGT\n+\nIIII\n@r2\nACGT\n+\nIII\n";
+        let mut reader = Reader::new(input);
+        reader.set_strict(true);
+        let records: Vec<_> = reader.records().collect();
+        assert_eq!(records[0].as_ref().unwrap().seq(), b"ACGT");
+        let error = records[1].as_ref().unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::InvalidData);
+        assert!(error.to_string().contains("r2"));
+        // Without the flag, the record is read as-is.
+        let records: Vec<_> = Reader::new(input).records().collect();
+        assert_eq!(records[1].as_ref().unwrap().quality(), b"III");
+        let input: &[u8] = b"@r1\nACGT\nIIII\n@r2\nACGT\n+\nIIII\n";
+        let mut reader = Reader::new(input);
+        reader.set_strict(true);
+        let mut record = Record::new();
+        let error = reader.read(&mut record).unwrap_err();
+        assert!(error.to_string().contains("'+'"));
+        let input: &[u8] = b"@r1\nACGU\n+\nIIII\n";
+        let mut reader = Reader::new(input);
+        reader.set_strict(true);
+        assert!(reader.read(&mut record).is_err());
+        let mut reader = Reader::new(input);
+        reader.set_strict(true);
+        reader.set_alphabet(b"ACGU");
+        reader.read(&mut record).unwrap();
+        assert_eq!(record.seq(), b"ACGU");
+    }
+    #[test]
+    fn quality_histogram_test() {
+        let record = Record::with_data("read", b"ACGTACGT", b"IIIIII#+");
+        let histogram = record.quality_histogram();
+        assert_eq!(histogram[40], 6);
+        assert_eq!(histogram[2], 1);
+        assert_eq!(histogram[10], 1);
+        assert_eq!(histogram.iter().sum::<usize>(), 8);
+        let record = Record::with_data("read", b"A", b"~");
+        assert_eq!(record.quality_histogram()[63], 1);
+    }
+    #[test]
+    fn canonical_id_test() {
+        let mate1 = Record::with_data("HWUSI-EAS100R:6:73:941:1973/1", b"A", b"I");
+        let mate2 = Record::with_data("HWUSI-EAS100R:6:73:941:1973/2", b"A", b"I");
+        assert_eq!(mate1.canonical_id(), "HWUSI-EAS100R:6:73:941:1973");
+        assert_eq!(mate1.canonical_id(), mate2.canonical_id());
+        let mate1 = Record::with_data(
+            "EAS139:136:FC706VJ:2:2104:15343:197393 1:Y:18:ATCACG",
+            b"A",
+            b"I",
+        );
+        let mate2 = Record::with_data(
+            "EAS139:136:FC706VJ:2:2104:15343:197393 2:N:18:ATCACG",
+            b"A",
+            b"I",
+        );
+        assert_eq!(
+            mate1.canonical_id(),
+            "EAS139:136:FC706VJ:2:2104:15343:197393"
+        );
+        assert_eq!(mate1.canonical_id(), mate2.canonical_id());
+        let single = Record::with_data("read/3", b"A", b"I");
+        assert_eq!(single.canonical_id(), "read/3");
+    }
+    #[test]
+    fn indices_test() {
+        let id = "A00123:8:H3:1:1101:1000:2000 1:N:0:ATCACG+GATCGT";
+        let dual = Record::with_data(id, b"A", b"I");
+        assert_eq!(dual.indices(), Some(("ATCACG", "GATCGT")));
+        let id = "EAS139:136:FC706VJ:2:2104:15343:197393 2:Y:18:ATCACG";
+        let single = Record::with_data(id, b"A", b"I");
+        assert_eq!(single.indices(), Some(("ATCACG", "")));
+        for id in [
+            "read1",
+            "read1 length=100",
+            "read1 1:N:0:",
+            "read1 1:X:0:ACGT",
+        ]
+        .iter()
+        {
+            assert_eq!(Record::with_data(id, b"A", b"I").indices(), None);
+        }
+    }
+    #[test]
+    fn to_fasta_test() {
+        let record = Record::with_data("read", b"ACGTNacgt", b"IIIIIIIII");
+        let fasta = record.to_fasta();
+        assert_eq!(fasta.id(), record.id());
+        assert_eq!(fasta.seq(), record.seq());
+        assert!(fasta.desc().is_none());
+    }
+    #[test]
+    fn extract_prefix_test() {
+        let record = Record::with_data("read", b"ACGTAAGGCCTT", b"ABCDEFGHIJKL");
+        let (trimmed, umi) = record.extract_prefix(4);
+        assert_eq!(umi, b"ACGT".to_vec());
+        assert_eq!(trimmed.id(), "read");
+        assert_eq!(trimmed.seq(), b"AAGGCCTT");
+        assert_eq!(trimmed.quality(), b"EFGHIJKL");
+        let (trimmed, umi) = record.extract_prefix(20);
+        assert_eq!(umi, record.seq().to_vec());
+        assert!(trimmed.seq().is_empty() && trimmed.quality().is_empty());
+        // A quality line shorter than the sequence.
+        let record = Record::with_data("read", b"ACGTAAGG", b"ABC");
+        let (trimmed, umi) = record.extract_prefix(4);
+        assert_eq!(umi, b"ACGT".to_vec());
+        assert_eq!(trimmed.seq(), b"AAGG");
+        assert!(trimmed.quality().is_empty());
+    }
+    #[test]
+    fn trim_polyx_test() {
+        let seq = b"ACGTTGCAAAAAAAAACAAAAAAaaa";
+        let qual = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ";
+        let record = Record::with_data("read", seq, qual);
+        let trimmed = record.trim_polyx(b'A', 10);
+        assert_eq!(trimmed.id(), "read");
+        assert_eq!(trimmed.seq(), b"ACGTTGC");
+        assert_eq!(trimmed.quality(), b"ABCDEFG");
+        assert_eq!(record.trim_polyx(b'A', 20).seq(), record.seq());
+        let record = Record::with_data("read", b"ACGTAGGGGGGGGGGGG", b"IIIIIIIIIIIIIIIII");
+        assert_eq!(record.trim_polyx(b'G', 10).seq(), b"ACGTA");
+        let clean = Record::with_data("read", b"ACGTACGTACGTACGA", b"IIIIIIIIIIIIIIII");
+        let trimmed = clean.trim_polyx(b'A', 5);
+        assert_eq!(trimmed.seq(), clean.seq());
+        assert_eq!(trimmed.quality(), clean.quality());
+        // A quality line shorter than the sequence.
+        let record = Record::with_data("read", b"ACGTAAAAAAAAAA", b"ABC");
+        let trimmed = record.trim_polyx(b'A', 5);
+        assert_eq!(trimmed.seq(), b"ACGT");
+        assert_eq!(trimmed.quality(), b"ABC");
+        let record = Record::with_data("read", b"ACGTAAAAAAAAAA", b"AB");
+        assert_eq!(record.trim_polyx(b'A', 5).quality(), b"AB");
+    }
+    #[test]
+    fn entropy_test() {
+        let poly_a = Record::with_data("polyA", &[b'A'; 50], &[b'I'; 50]);
+        assert!(poly_a.shannon_entropy().abs() < 1e-9);
+        let mut state: u64 = 88_172_645_463_325_252;
+        let seq: Vec<u8> = (0..1000)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 7;
+                state ^= state << 17;
+                b"ACGT"[(state % 4) as usize]
+            })
+            .collect();
+        let random = Record::with_data("random", &seq, &vec![b'I'; seq.len()]);
+        assert!(random.shannon_entropy() > 1.99);
+        let repeat = Record::with_data("repeat", b"ATATATAT", b"IIIIIIII");
+        assert_eq!(repeat.shannon_entropy(), 1.);
+        assert_eq!(Record::new().shannon_entropy(), 0.);
+        let input = format!("{}\n{}\n{}\n", poly_a, random, repeat);
+        let ids: Vec<_> = Reader::new(input.as_bytes())
+            .records()
+            .min_complexity(1.5)
+            .map(|r| r.unwrap().id().to_string())
+            .collect();
+        assert_eq!(ids, vec!["random"]);
+    }
+    #[test]
+    fn per_position_quality_test() {
+        let records = vec![
+            Record::with_data("read1", b"ACGT", b"I#+5"),
+            Record::with_data("read2", b"ACGTAC", b"+I+5II"),
+        ];
+        let summary = per_position_quality(&records);
+        assert_eq!(
+            summary,
+            vec![
+                (25., 10, 40),
+                (21., 2, 40),
+                (10., 10, 10),
+                (20., 20, 20),
+                (40., 40, 40),
+                (40., 40, 40)
+            ]
+        );
+        assert!(per_position_quality(&[]).is_empty());
+    }
+    #[test]
+    fn writer_round_trip() {
+        let records = parse_into_vec("./testdata/test.fastq").unwrap();
+        let mut writer = Writer::new(vec![]);
+        for record in records.iter() {
+            writer.write_record(record).unwrap();
+        }
+        let output = writer.into_inner().unwrap();
+        assert_eq!(output, std::fs::read("./testdata/test.fastq").unwrap());
+    }
+    #[cfg(feature = "flate2")]
+    #[test]
+    fn gzipped_round_trip() {
+        let records = parse_into_vec("./testdata/test.fastq").unwrap();
+        let mut file = std::env::temp_dir();
+        file.push("bio_utils_gzipped_round_trip_test.fastq.gz");
+        let mut writer = Writer::to_file(&file).unwrap();
+        for record in records.iter() {
+            writer.write_record(record).unwrap();
+        }
+        writer.finish().unwrap();
+        let magic = std::fs::read(&file).unwrap();
+        assert!(magic.starts_with(&[0x1f, 0x8b]));
+        let read: Vec<_> = Reader::from_file_auto(&file)
+            .unwrap()
+            .records()
+            .map(|r| r.unwrap())
+            .collect();
+        std::fs::remove_file(&file).unwrap();
+        let plain: Vec<_> = Reader::from_file_auto("./testdata/test.fastq")
+            .unwrap()
+            .records()
+            .map(|r| r.unwrap())
+            .collect();
+        assert_eq!(plain.len(), records.len());
+        assert_eq!(read.len(), records.len());
+        for (x, y) in read.iter().zip(records.iter()) {
+            assert_eq!(
+                (x.id(), x.seq(), x.quality()),
+                (y.id(), y.seq(), y.quality())
+            );
+        }
+    }
+    #[test]
+    fn quality_histogram_file() {
+        let histogram = quality_histogram_from_file("./testdata/test.fastq").unwrap();
+        assert_eq!(histogram[40], 15);
+        assert_eq!(histogram[2], 15);
+        assert_eq!(histogram[10], 10);
+        assert_eq!(histogram.iter().sum::<usize>(), 40);
+    }
+}
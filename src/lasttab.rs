@@ -1,34 +1,8 @@
 //! LastTAB is a struct to represent an alignment record produced by `last` program.
 
-/// The direction of the alignment.
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Hash)]
-pub enum Strand {
-    /// The strand is forward.
-    Forward,
-    /// The strand is reverse. So is the alignment coordinate.
-    /// For example, if the length of sequence is 90, the start position is 10,
-    /// the length of the alignment is 30,
-    /// and the direction is `Strand::Reverse`, then the start position
-    /// with respect to forward strand is 90 - 10 - 30 + 1.
-    Reverse,
-}
-
-impl std::fmt::Display for Strand {
-    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
-        match self {
-            Strand::Forward => write!(f, "+"),
-            Strand::Reverse => write!(f, "-"),
-        }
-    }
-}
-impl Strand {
-    pub fn is_forward(self) -> bool {
-        match self {
-            Self::Forward => true,
-            Self::Reverse => false,
-        }
-    }
-}
+/// Kept for compatibility. Use [`crate::Strand`] instead.
+#[deprecated(note = "use crate::Strand")]
+pub type Strand = crate::Strand;
 
 /// This is the information of alignment for a single strand.
 /// Usually, a TAB-formatted alignment is losslessly represented by two `AlignInfo`
@@ -45,7 +19,7 @@ pub struct AlignInfo {
     /// the alignment region is [seqstart..seqstart+matchlen).
     matchlen: usize,
     /// The direction of the alignment.
-    direction: Strand,
+    direction: crate::Strand,
     /// The length of the sequence.
     seqlen: usize,
 }
@@ -66,9 +40,9 @@ impl AlignInfo {
         let seqstart: usize = splits[1].parse().ok()?;
         let matchlen = splits[2].parse().ok()?;
         let direction = if splits[3] == "+" {
-            Strand::Forward
+            crate::Strand::Forward
         } else {
-            Strand::Reverse
+            crate::Strand::Reverse
         };
         let seqlen = splits[4].parse().ok()?;
         Some(Self {
@@ -79,16 +53,31 @@ impl AlignInfo {
             seqlen,
         })
     }
+    // The aligned region of the sequence, in the direction of the alignment.
+    fn aligned_region(&self, seq: &[u8]) -> Option<Vec<u8>> {
+        if seq.len() != self.seqlen {
+            return None;
+        }
+        let start = self.seqstart_from_forward();
+        let region = seq.get(start..start + self.matchlen)?;
+        match self.direction {
+            crate::Strand::Forward => Some(region.to_vec()),
+            crate::Strand::Reverse => Some(crate::revcmp(region)),
+        }
+    }
     fn seqstart_from_forward(&self) -> usize {
         match self.direction {
-            Strand::Forward => self.seqstart,
-            Strand::Reverse => self.seqlen - self.matchlen - self.seqstart,
+            crate::Strand::Forward => self.seqstart,
+            crate::Strand::Reverse => self.seqlen - self.matchlen - self.seqstart,
         }
     }
 }
 
 use crate::sam::Record;
 use std::collections::HashMap;
+/// Convert a SAM record into a LastTAB. The `AS` tag is required for the score.
+/// As the TAB format does not distinguish mismatches, `M`, `=`, and `X` all become [`Op::Match`].
+/// To resolve the mismatches by the MD tag, use [`Record::cigar_with_mismatches`].
 pub fn try_from(value: &Record, length: &HashMap<String, usize>) -> Result<LastTAB, &'static str> {
     if value.pos() == 0 {
         return Err("Alignment Invalid");
@@ -137,7 +126,7 @@ pub fn try_from(value: &Record, length: &HashMap<String, usize>) -> Result<LastT
         seqname: value.r_name().to_string(),
         seqstart: value.pos() - 1,
         matchlen: matchlen_1,
-        direction: Strand::Forward,
+        direction: crate::Strand::Forward,
         seqlen: *seq1_len,
     };
     let matchlen_2 = cigar
@@ -161,9 +150,9 @@ pub fn try_from(value: &Record, length: &HashMap<String, usize>) -> Result<LastT
         })
         .sum::<_>();
     let direction = if value.is_forward() {
-        Strand::Forward
+        crate::Strand::Forward
     } else {
-        Strand::Reverse
+        crate::Strand::Reverse
     };
     let seq2_information = AlignInfo {
         seqname: value.q_name().to_string(),
@@ -172,6 +161,7 @@ pub fn try_from(value: &Record, length: &HashMap<String, usize>) -> Result<LastT
         direction,
         seqlen: total,
     };
+    normalize_ops(&mut alignment);
     let lt = LastTAB {
         score,
         alignment,
@@ -234,36 +224,22 @@ impl Eq for LastTAB {}
 impl LastTAB {
     pub fn from_line(line: &str) -> Option<Self> {
         let line: Vec<&str> = line.split('\t').collect();
+        if line.len() < 12 {
+            return None;
+        }
         let score: u64 = line[0].parse().ok()?;
         let seq1_information = AlignInfo::from_splits(&line[1..=5])?;
         let seq2_information = AlignInfo::from_splits(&line[6..=10])?;
-        let alignment = line[11].split(',').fold(vec![], |mut res, op| {
-            Op::from_string(&mut res, op);
-            res
-        });
+        let mut alignment = vec![];
+        for op in line[11].split(',') {
+            Op::from_string(&mut alignment, op)?;
+        }
         let (mut eg2, mut e) = (2., 3.); // Dummy values
-        if line.len() > 13 {
-            if line[12].starts_with("E=") {
-                e = match line[12][2..].parse() {
-                    Ok(res) => res,
-                    Err(why) => panic!("{},{}", why, &line[12][2..]),
-                };
-            } else if line[12].starts_with("EG2=") {
-                eg2 = match line[12][4..].parse() {
-                    Ok(res) => res,
-                    Err(why) => panic!("{},{}", why, &line[12][4..]),
-                };
-            };
-            if line[13].starts_with("E=") {
-                e = match line[13][2..].parse() {
-                    Ok(res) => res,
-                    Err(why) => panic!("{},{}", why, &line[13][2..]),
-                };
-            } else if line[13].starts_with("EG2=") {
-                eg2 = match line[13][4..].parse() {
-                    Ok(res) => res,
-                    Err(why) => panic!("{},{}", why, &line[13][4..]),
-                };
+        for field in line[12..].iter() {
+            if let Some(value) = field.strip_prefix("E=") {
+                e = value.parse().ok()?;
+            } else if let Some(value) = field.strip_prefix("EG2=") {
+                eg2 = value.parse().ok()?;
             };
         }
         Some(Self {
@@ -312,10 +288,10 @@ impl LastTAB {
     pub fn seq2_end_from_forward(&self) -> usize {
         self.seq2_start_from_forward() + self.seq2_matchlen()
     }
-    pub fn seq1_direction(&self) -> Strand {
+    pub fn seq1_direction(&self) -> crate::Strand {
         self.seq1_information.direction
     }
-    pub fn seq2_direction(&self) -> Strand {
+    pub fn seq2_direction(&self) -> crate::Strand {
         self.seq2_information.direction
     }
     pub fn seq1_len(&self) -> usize {
@@ -333,6 +309,63 @@ impl LastTAB {
     pub fn eg2_score(&self) -> f64 {
         self.eg2
     }
+    /// Return the alignment with the roles of seq1 and seq2 exchanged.
+    /// The insertions to seq1 become the insertions to seq2, and vice versa.
+    pub fn swap(&self) -> Self {
+        let alignment = self
+            .alignment
+            .iter()
+            .map(|op| match *op {
+                Op::Match(l) => Op::Match(l),
+                Op::Seq1In(l) => Op::Seq2In(l),
+                Op::Seq2In(l) => Op::Seq1In(l),
+            })
+            .collect();
+        Self {
+            seq1_information: self.seq2_information.clone(),
+            seq2_information: self.seq1_information.clone(),
+            score: self.score,
+            alignment,
+            eg2: self.eg2,
+            e: self.e,
+        }
+    }
+    /// Count the mismatches by walking the alignment over the sequences(case-insensitive).
+    /// Both `seq1` and `seq2` should be the whole forward-strand sequences. The reverse strands are
+    /// handled by the directions of the alignment.
+    /// Return `None` if the lengths of the sequences differ from the ones in this record, or
+    /// the alignment runs out of the aligned regions.
+    pub fn count_mismatches(&self, seq1: &[u8], seq2: &[u8]) -> Option<usize> {
+        let seq1 = self.seq1_information.aligned_region(seq1)?;
+        let seq2 = self.seq2_information.aligned_region(seq2)?;
+        let (mut pos1, mut pos2, mut mismatches) = (0, 0, 0);
+        for op in self.alignment.iter() {
+            match *op {
+                Op::Match(l) => {
+                    let region1 = seq1.get(pos1..pos1 + l)?;
+                    let region2 = seq2.get(pos2..pos2 + l)?;
+                    mismatches += region1
+                        .iter()
+                        .zip(region2.iter())
+                        .filter(|(a, b)| !a.eq_ignore_ascii_case(b))
+                        .count();
+                    pos1 += l;
+                    pos2 += l;
+                }
+                Op::Seq1In(l) => pos2 += l,
+                Op::Seq2In(l) => pos1 += l,
+            }
+        }
+        if seq1.len() < pos1 || seq2.len() < pos2 {
+            return None;
+        }
+        Some(mismatches)
+    }
+    /// Merge the consecutive operations of the same kind, such as `Match(3), Match(2)` into `Match(5)`.
+    /// See [`normalize_ops`].
+    pub fn normalize(&mut self) {
+        normalize_ops(&mut self.alignment);
+    }
     // Return alignment length. Not the length of the reference nor the query.
     pub fn alignment_length(&self) -> usize {
         self.alignment
@@ -346,6 +379,36 @@ impl LastTAB {
     }
 }
 
+/// Group the alignments by the name of the query(seq2), as `last-split` reports a read mapped to
+/// several segments of the reference as several alignments.
+/// In each group, the alignments are sorted by their start position on the forward strand of the query,
+/// so the pieces of a split mapping appear in the order along the read.
+pub fn group_by_query(records: &[LastTAB]) -> HashMap<&str, Vec<&LastTAB>> {
+    let mut groups: HashMap<_, Vec<_>> = HashMap::new();
+    for record in records.iter() {
+        groups.entry(record.seq2_name()).or_default().push(record);
+    }
+    for group in groups.values_mut() {
+        group.sort_by_key(|record| record.seq2_start_from_forward());
+    }
+    groups
+}
+
+/// Merge the consecutive operations of the same kind, and remove operations of zero length.
+pub fn normalize_ops(ops: &mut Vec<Op>) {
+    let mut normalized: Vec<Op> = Vec::with_capacity(ops.len());
+    for &op in ops.iter() {
+        match (normalized.last_mut(), op) {
+            (_, Op::Match(0)) | (_, Op::Seq1In(0)) | (_, Op::Seq2In(0)) => {}
+            (Some(Op::Match(l)), Op::Match(m))
+            | (Some(Op::Seq1In(l)), Op::Seq1In(m))
+            | (Some(Op::Seq2In(l)), Op::Seq2In(m)) => *l += m,
+            _ => normalized.push(op),
+        }
+    }
+    *ops = normalized;
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Hash)]
 pub enum Op {
     /// Match of `usize` length.
@@ -370,11 +433,18 @@ impl std::fmt::Display for Op {
 }
 
 impl Op {
-    fn from_string(res: &mut Vec<Op>, input: &str) {
+    /// Parse one comma-separated element of the alignment column.
+    /// `x:y` is an unaligned gap with `x` bases in seq1 and `y` bases in seq2,
+    /// so it becomes `Seq2In(x)` followed by `Seq1In(y)`, dropping the zero-length side.
+    /// This is the inverse of the `Display` implementation.
+    fn from_string(res: &mut Vec<Op>, input: &str) -> Option<()> {
         if input.contains(':') {
             let mut input = input.split(':');
-            let seq1 = input.next().and_then(|e| e.parse().ok()).unwrap();
-            let seq2 = input.next().and_then(|e| e.parse().ok()).unwrap();
+            let seq1 = input.next()?.parse().ok()?;
+            let seq2 = input.next()?.parse().ok()?;
+            if input.next().is_some() {
+                return None;
+            }
             if seq1 != 0 {
                 res.push(Op::Seq2In(seq1));
             }
@@ -382,8 +452,9 @@ impl Op {
                 res.push(Op::Seq1In(seq2));
             }
         } else {
-            res.push(Op::Match(input.parse().unwrap()))
+            res.push(Op::Match(input.parse().ok()?))
         }
+        Some(())
     }
 }
 
@@ -399,12 +470,12 @@ mod tests {
         assert_eq!(aln.seq1_name(), "tig00000001");
         assert_eq!(aln.seq1_start(), 98045);
         assert_eq!(aln.seq1_matchlen(), 539);
-        assert_eq!(aln.seq1_direction(), Strand::Forward);
+        assert_eq!(aln.seq1_direction(), crate::Strand::Forward);
         assert_eq!(aln.seq1_len(), 261026);
         assert_eq!(aln.seq2_name(), "m54113_160913_184949/5570667/0_1125");
         assert_eq!(aln.seq2_start(), 4);
         assert_eq!(aln.seq2_matchlen(), 527);
-        assert_eq!(aln.seq2_direction(), Strand::Reverse);
+        assert_eq!(aln.seq2_direction(), crate::Strand::Reverse);
         assert_eq!(aln.seq2_len(), 1125);
         use Op::*;
         assert_eq!(
@@ -428,4 +499,137 @@ mod tests {
         assert_eq!(aln.seq2_start_from_forward(), 1125 - 527 - 4);
         assert_eq!(aln.seq2_end_from_forward(), 1125 - 4);
     }
+    #[test]
+    fn group_by_query_test() {
+        let first = "500\tchr1\t1000\t500\t+\t10000\tread1\t500\t500\t+\t1000\t500\tEG2=0\tE=0";
+        let second = "400\tchr2\t2000\t480\t-\t8000\tread1\t0\t480\t+\t1000\t480\tEG2=0\tE=0";
+        let records: Vec<_> = [first, LAST_INPUT, second]
+            .iter()
+            .map(|line| LastTAB::from_line(line).unwrap())
+            .collect();
+        let groups = group_by_query(&records);
+        assert_eq!(groups.len(), 2);
+        let read1 = &groups["read1"];
+        assert_eq!(read1.len(), 2);
+        assert_eq!(read1[0].seq1_name(), "chr2");
+        assert_eq!(read1[1].seq1_name(), "chr1");
+        assert_eq!(groups["m54113_160913_184949/5570667/0_1125"].len(), 1);
+        assert!(group_by_query(&[]).is_empty());
+    }
+    #[test]
+    fn normalize_test() {
+        use Op::*;
+        let mut ops = vec![
+            Match(3),
+            Match(2),
+            Seq1In(1),
+            Seq1In(1),
+            Match(0),
+            Seq2In(4),
+            Match(1),
+            Seq2In(0),
+            Match(2),
+        ];
+        normalize_ops(&mut ops);
+        assert_eq!(ops, vec![Match(5), Seq1In(2), Seq2In(4), Match(3)]);
+        let mut aln = LastTAB::from_line(LAST_INPUT).unwrap();
+        let before = aln.alignment().to_vec();
+        aln.normalize();
+        assert_eq!(aln.alignment(), before.as_slice());
+    }
+    #[test]
+    fn count_mismatches_test() {
+        let aln = LastTAB::from_line(LAST_INPUT).unwrap();
+        let seq1: Vec<u8> = (0..261026).map(|i| b"ACGT"[(i * 7 + i / 5) % 4]).collect();
+        // Build the aligned region of seq2(on the reverse strand) from the alignment.
+        let region1 = &seq1[98045..98045 + 539];
+        let (mut pos1, mut region2) = (0, vec![]);
+        for op in aln.alignment() {
+            match *op {
+                Op::Match(l) => {
+                    region2.extend_from_slice(&region1[pos1..pos1 + l]);
+                    pos1 += l;
+                }
+                Op::Seq1In(l) => region2.extend(std::iter::repeat_n(b'T', l)),
+                Op::Seq2In(l) => pos1 += l,
+            }
+        }
+        region2[2] = if region2[2] == b'A' { b'C' } else { b'A' };
+        region2[20] = if region2[20] == b'A' { b'C' } else { b'A' };
+        region2.resize(527, b'G');
+        let mut seq2_revcmp = vec![b'C'; 4];
+        seq2_revcmp.extend_from_slice(&region2);
+        seq2_revcmp.resize(1125, b'G');
+        let seq2 = crate::revcmp(&seq2_revcmp);
+        assert_eq!(aln.count_mismatches(&seq1, &seq2), Some(2));
+        assert_eq!(aln.count_mismatches(&seq2, &seq1), None);
+        assert_eq!(aln.count_mismatches(&seq1, &seq2[1..]), None);
+    }
+    #[test]
+    fn swap_test() {
+        let aln = LastTAB::from_line(LAST_INPUT).unwrap();
+        let swapped = aln.swap();
+        assert_eq!(swapped.seq1_name(), aln.seq2_name());
+        assert_eq!(swapped.seq2_name(), aln.seq1_name());
+        assert_eq!(swapped.seq1_direction(), crate::Strand::Reverse);
+        assert_eq!(swapped.seq1_matchlen(), 527);
+        assert_eq!(swapped.alignment()[1], Op::Seq1In(1));
+        assert_eq!(swapped.alignment()[7], Op::Seq2In(1));
+        assert_eq!(swapped, aln);
+        let twice = swapped.swap();
+        assert_eq!(format!("{}", twice), format!("{}", aln));
+        assert_eq!(twice.alignment(), aln.alignment());
+    }
+    #[test]
+    fn display_round_trip_test() {
+        let aln = LastTAB::from_line(LAST_INPUT).unwrap();
+        let line = format!("{}", aln);
+        let reparsed = LastTAB::from_line(&line).unwrap();
+        assert_eq!(reparsed.alignment(), aln.alignment());
+        assert_eq!(reparsed.score(), aln.score());
+        assert_eq!(reparsed.seq1_start(), aln.seq1_start());
+        assert_eq!(reparsed.seq2_direction(), aln.seq2_direction());
+        assert_eq!(format!("{}", reparsed), line);
+        // An unaligned gap with bases on both sides expands into two gaps.
+        let gap = LAST_INPUT.replace("0:1,3", "2:1,3");
+        let aln = LastTAB::from_line(&gap).unwrap();
+        assert_eq!(aln.alignment()[7..9], [Op::Seq2In(2), Op::Seq1In(1)]);
+        let reparsed = LastTAB::from_line(&format!("{}", aln)).unwrap();
+        assert_eq!(reparsed.alignment(), aln.alignment());
+        assert!(LastTAB::from_line(&LAST_INPUT.replace("0:1,3", "0:x,3")).is_none());
+        assert!(LastTAB::from_line(&LAST_INPUT.replace("0:1,3", "0:1:2,3")).is_none());
+        assert!(LastTAB::from_line(&LAST_INPUT.replace("E=6.6e-95", "E=abc")).is_none());
+        assert!(LastTAB::from_line(&LAST_INPUT.replace("EG2=8.2e-86", "EG2=")).is_none());
+        let mut state: u64 = 24;
+        let mut xorshift = || {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            state
+        };
+        for _ in 0..100 {
+            let mut ops: Vec<_> = (0..1 + xorshift() % 30)
+                .map(|_| {
+                    let len = 1 + (xorshift() % 20) as usize;
+                    match xorshift() % 3 {
+                        0 => Op::Match(len),
+                        1 => Op::Seq1In(len),
+                        _ => Op::Seq2In(len),
+                    }
+                })
+                .collect();
+            normalize_ops(&mut ops);
+            let ops: Vec<_> = ops.iter().map(|op| format!("{}", op)).collect();
+            let line = format!(
+                "100\tseq1\t10\t50\t+\t1000\tseq2\t20\t50\t-\t1000\t{}\tEG2=1.5e-10\tE=3e-12",
+                ops.join(",")
+            );
+            let aln = LastTAB::from_line(&line).unwrap();
+            let displayed: Vec<_> = aln.alignment().iter().map(|op| format!("{}", op)).collect();
+            assert_eq!(displayed, ops);
+            let reparsed = LastTAB::from_line(&format!("{}", aln)).unwrap();
+            assert_eq!(reparsed.alignment(), aln.alignment());
+            assert_eq!(format!("{}", reparsed), format!("{}", aln));
+        }
+    }
 }
@@ -1,30 +1,561 @@
 #[macro_use]
 extern crate serde;
 pub mod alignments;
+pub mod codon;
 pub mod fasta;
 pub mod fastq;
 pub mod lasttab;
 pub mod maf;
 pub mod paf;
 pub mod sam;
-#[cfg(test)]
-mod tests {
-    #[test]
-    fn it_works() {
-        assert_eq!(2 + 2, 4);
+
+/// The direction of the alignment.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Hash)]
+pub enum Strand {
+    /// The strand is forward.
+    Forward,
+    /// The strand is reverse. So is the alignment coordinate.
+    /// For example, if the length of sequence is 90, the start position is 10,
+    /// the length of the alignment is 30,
+    /// and the direction is `Strand::Reverse`, then the start position
+    /// with respect to forward strand is 90 - 10 - 30 + 1.
+    Reverse,
+}
+
+impl std::fmt::Display for Strand {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Strand::Forward => write!(f, "+"),
+            Strand::Reverse => write!(f, "-"),
+        }
     }
 }
+impl Strand {
+    pub fn is_forward(self) -> bool {
+        match self {
+            Self::Forward => true,
+            Self::Reverse => false,
+        }
+    }
+    /// Return the opposite strand.
+    pub fn reverse(self) -> Self {
+        match self {
+            Self::Forward => Self::Reverse,
+            Self::Reverse => Self::Forward,
+        }
+    }
+}
+
+/// `true` is the forward strand, as in `PAF::relstrand`.
+impl std::convert::From<bool> for Strand {
+    fn from(is_forward: bool) -> Self {
+        if is_forward {
+            Self::Forward
+        } else {
+            Self::Reverse
+        }
+    }
+}
+
+/// An output file, gzipped or not, opened by `to_file` of [`fasta::Writer`] and [`fastq::Writer`].
+/// Call [`OutputFile::finish`] to write the gzip trailer and see the error, if any.
+/// Otherwise, the stream is finished when it is dropped, and the error is lost.
+#[derive(Debug)]
+pub enum OutputFile {
+    Plain(std::fs::File),
+    #[cfg(feature = "flate2")]
+    Gzip(flate2::write::GzEncoder<std::fs::File>),
+}
 
+impl OutputFile {
+    /// Create the file at `path`. If the path ends with `.gz`, the output is gzipped,
+    /// which requires the `flate2` feature.
+    fn create<P: AsRef<std::path::Path>>(path: P) -> std::io::Result<Self> {
+        let is_gz = path
+            .as_ref()
+            .extension()
+            .map(|ext| ext == "gz")
+            .unwrap_or(false);
+        #[cfg(not(feature = "flate2"))]
+        {
+            if is_gz {
+                let message = "writing a gzipped file requires the `flate2` feature";
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::Unsupported,
+                    message,
+                ));
+            }
+        }
+        let file = std::fs::File::create(path)?;
+        #[cfg(feature = "flate2")]
+        {
+            if is_gz {
+                let encoder = flate2::write::GzEncoder::new(file, flate2::Compression::default());
+                return Ok(Self::Gzip(encoder));
+            }
+        }
+        Ok(Self::Plain(file))
+    }
+    /// Flush the file, and finish the gzip stream if it is gzipped.
+    pub fn finish(self) -> std::io::Result<std::fs::File> {
+        use std::io::Write;
+        match self {
+            Self::Plain(mut file) => file.flush().map(|_| file),
+            #[cfg(feature = "flate2")]
+            Self::Gzip(encoder) => encoder.finish(),
+        }
+    }
+}
+
+impl std::io::Write for OutputFile {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        match self {
+            Self::Plain(file) => file.write(buf),
+            #[cfg(feature = "flate2")]
+            Self::Gzip(encoder) => encoder.write(buf),
+        }
+    }
+    fn flush(&mut self) -> std::io::Result<()> {
+        match self {
+            Self::Plain(file) => file.flush(),
+            #[cfg(feature = "flate2")]
+            Self::Gzip(encoder) => encoder.flush(),
+        }
+    }
+}
+
+/// Return the reverse complement of the sequence.
+/// All the IUPAC ambiguity codes are complemented, preserving the case.
+/// `N` and gaps(`-`) are kept as-is, and any other byte is converted into `N`.
 #[inline]
 pub fn revcmp(seq: &[u8]) -> Vec<u8> {
-    seq.iter()
-        .rev()
+    seq.iter().rev().map(|&e| complement_base(e)).collect()
+}
+
+/// Return the reverse complement of the RNA sequence.
+/// `U`/`u` is complementary to `A`/`a`, and `U` is emitted where [`revcmp`] would emit `T`.
+/// `T`/`t` is also accepted and treated in the same way as `U`/`u`.
+pub fn revcmp_rna(seq: &[u8]) -> Vec<u8> {
+    seq.iter().rev().map(|&e| complement_base_rna(e)).collect()
+}
+
+#[inline]
+fn complement_base_rna(base: u8) -> u8 {
+    match base {
+        b'A' => b'U',
+        b'a' => b'u',
+        b'U' => b'A',
+        b'u' => b'a',
+        _ => complement_base(base),
+    }
+}
+
+/// Return the complement of the sequence, without reversing it.
+/// See [`revcmp`] for the treatment of each byte.
+pub fn complement(seq: &[u8]) -> Vec<u8> {
+    seq.iter().map(|&e| complement_base(e)).collect()
+}
+
+/// Return the complement of the base. The same table as [`revcmp`] is used.
+#[inline]
+pub fn complement_base(base: u8) -> u8 {
+    iupac_complement(base).unwrap_or(b'N')
+}
+
+/// Return the reverse complement of the sequence, or the first invalid byte if any.
+/// The valid bytes are the same as [`revcmp`], except that unknown bytes are not allowed.
+pub fn try_revcmp(seq: &[u8]) -> Result<Vec<u8>, u8> {
+    match seq.iter().find(|&&e| iupac_complement(e).is_none()) {
+        Some(&invalid) => Err(invalid),
+        None => Ok(revcmp(seq)),
+    }
+}
+
+// Complement of the IUPAC nucleotide code, if it is valid.
+#[inline]
+fn iupac_complement(base: u8) -> Option<u8> {
+    let cmp = match base {
+        b'A' => b'T',
+        b'C' => b'G',
+        b'G' => b'C',
+        b'T' => b'A',
+        b'R' => b'Y',
+        b'Y' => b'R',
+        b'S' => b'S',
+        b'W' => b'W',
+        b'K' => b'M',
+        b'M' => b'K',
+        b'B' => b'V',
+        b'V' => b'B',
+        b'D' => b'H',
+        b'H' => b'D',
+        b'N' => b'N',
+        b'a' => b't',
+        b'c' => b'g',
+        b'g' => b'c',
+        b't' => b'a',
+        b'r' => b'y',
+        b'y' => b'r',
+        b's' => b's',
+        b'w' => b'w',
+        b'k' => b'm',
+        b'm' => b'k',
+        b'b' => b'v',
+        b'v' => b'b',
+        b'd' => b'h',
+        b'h' => b'd',
+        b'n' => b'n',
+        b'-' => b'-',
+        _ => return None,
+    };
+    Some(cmp)
+}
+
+/// Transcribe the DNA sequence into RNA, i.e., convert `T`/`t` into `U`/`u`.
+/// Other bytes are left untouched.
+pub fn transcribe(dna: &[u8]) -> Vec<u8> {
+    dna.iter()
+        .map(|&e| match e {
+            b'T' => b'U',
+            b't' => b'u',
+            _ => e,
+        })
+        .collect()
+}
+
+/// Reverse-transcribe the RNA sequence into DNA, i.e., convert `U`/`u` into `T`/`t`.
+/// Other bytes are left untouched.
+pub fn reverse_transcribe(rna: &[u8]) -> Vec<u8> {
+    rna.iter()
         .map(|&e| match e {
-            b'A' | b'a' => b'T',
-            b'C' | b'c' => b'G',
-            b'G' | b'g' => b'C',
-            b'T' | b't' => b'A',
-            _ => unreachable!(),
+            b'U' => b'T',
+            b'u' => b't',
+            _ => e,
+        })
+        .collect()
+}
+
+/// Return the lexicographically smaller one of the k-mer and its reverse complement.
+pub fn canonical_kmer(kmer: &[u8]) -> Vec<u8> {
+    let rev = revcmp(kmer);
+    if rev.as_slice() < kmer {
+        rev
+    } else {
+        kmer.to_vec()
+    }
+}
+
+/// Pack the k-mer into a `u64`, two bits per base(A=0, C=1, G=2, T=3).
+/// The first base is at the most significant position.
+/// Return `None` if the k-mer is longer than 32 or contains a non-ACGT base.
+/// The case is ignored.
+pub fn kmer_to_u64(kmer: &[u8]) -> Option<u64> {
+    if 32 < kmer.len() {
+        return None;
+    }
+    kmer.iter().try_fold(0u64, |packed, &base| {
+        Some((packed << 2) | base_to_2bit(base)? as u64)
+    })
+}
+
+#[inline]
+fn base_to_2bit(base: u8) -> Option<u8> {
+    match base {
+        b'A' | b'a' => Some(0),
+        b'C' | b'c' => Some(1),
+        b'G' | b'g' => Some(2),
+        b'T' | b't' => Some(3),
+        _ => None,
+    }
+}
+
+/// Unpack the `k`-mer packed by [`kmer_to_u64`]. The bases are in uppercase.
+pub fn u64_to_kmer(packed: u64, k: usize) -> Vec<u8> {
+    (0..k)
+        .rev()
+        .map(|i| b"ACGT"[((packed >> (2 * i)) & 0b11) as usize])
+        .collect()
+}
+
+/// Return true if the sequence consists only of `ACGTN`, in either case.
+pub fn is_valid_dna(seq: &[u8]) -> bool {
+    first_invalid_base(seq).is_none()
+}
+
+/// Return the position and the byte of the first base not in `ACGTN`(in either case), if any.
+pub fn first_invalid_base(seq: &[u8]) -> Option<(usize, u8)> {
+    seq.iter()
+        .enumerate()
+        .find(|(_, b)| !b"ACGTNacgtn".contains(b))
+        .map(|(i, &b)| (i, b))
+}
+
+/// Return the position and the byte of the first byte which is neither
+/// an IUPAC nucleotide code nor a gap(`-`), if any.
+/// These are the bytes [`try_revcmp`] accepts.
+pub fn first_invalid_iupac_base(seq: &[u8]) -> Option<(usize, u8)> {
+    seq.iter()
+        .enumerate()
+        .find(|(_, &b)| iupac_complement(b).is_none())
+        .map(|(i, &b)| (i, b))
+}
+
+/// Pack the sequence into bytes, four bases per byte, in the same encoding as [`kmer_to_u64`].
+/// The last byte is padded with zeros if the length is not a multiple of four,
+/// so the length should be kept to unpack it.
+/// Return `None` if the sequence contains a non-ACGT base.
+pub fn pack_2bit(seq: &[u8]) -> Option<Vec<u8>> {
+    seq.chunks(4)
+        .map(|chunk| {
+            chunk
+                .iter()
+                .enumerate()
+                .try_fold(0u8, |packed, (i, &base)| {
+                    Some(packed | (base_to_2bit(base)? << (6 - 2 * i)))
+                })
         })
         .collect()
 }
+
+/// Unpack the first `len` bases of the sequence packed by [`pack_2bit`]. The bases are in uppercase.
+/// Panics if `len` is more than `4 * packed.len()`, the number of the bases in `packed` including the padding.
+pub fn unpack_2bit(packed: &[u8], len: usize) -> Vec<u8> {
+    assert!(
+        len <= 4 * packed.len(),
+        "{} bases are requested but only {} bytes are packed",
+        len,
+        packed.len()
+    );
+    (0..len)
+        .map(|i| b"ACGT"[((packed[i / 4] >> (6 - 2 * (i % 4))) & 0b11) as usize])
+        .collect()
+}
+
+/// Return the number of `A`, `C`, `G`, `T`, and the other bytes, in this order.
+/// The case is ignored.
+pub fn base_composition(seq: &[u8]) -> [usize; 5] {
+    let mut composition = [0; 5];
+    for base in seq {
+        match base {
+            b'A' | b'a' => composition[0] += 1,
+            b'C' | b'c' => composition[1] += 1,
+            b'G' | b'g' => composition[2] += 1,
+            b'T' | b't' => composition[3] += 1,
+            _ => composition[4] += 1,
+        }
+    }
+    composition
+}
+
+/// Return the fraction of `G` and `C` among `A`, `C`, `G`, and `T`.
+/// The other bytes such as `N` are not counted. If there is no ACGT, return 0.
+pub fn gc_content(seq: &[u8]) -> f64 {
+    let [a, c, g, t, _] = base_composition(seq);
+    match a + c + g + t {
+        0 => 0.,
+        total => (g + c) as f64 / total as f64,
+    }
+}
+
+/// Return the melting temperature (Tm, in Celsius) of the primer.
+/// If the primer is shorter than 14 bases, [`melting_temp_wallace`] is used.
+/// Otherwise, [`melting_temp_nn`] is used.
+pub fn melting_temp(primer: &[u8]) -> f64 {
+    if primer.len() < 14 {
+        melting_temp_wallace(primer)
+    } else {
+        melting_temp_nn(primer)
+    }
+}
+
+/// Return the melting temperature by the Wallace rule, `2*(A+T) + 4*(G+C)`.
+/// This rule is only valid for short oligos, up to 13 or 14 bases long.
+/// Bases other than ACGT are not counted.
+pub fn melting_temp_wallace(primer: &[u8]) -> f64 {
+    let [a, c, g, t, _] = base_composition(primer);
+    (2 * (a + t) + 4 * (g + c)) as f64
+}
+
+/// Return the melting temperature by the nearest-neighbor method,
+/// with the parameters by SantaLucia (1998) and the salt correction for 50mM Na+.
+/// The concentration of each strand is assumed to be 25nM.
+/// This method is valid for primers longer than 14 bases, up to about 60 bases.
+/// Dinucleotide steps containing a base other than ACGT are skipped.
+pub fn melting_temp_nn(primer: &[u8]) -> f64 {
+    // ΔH(kcal/mol) and ΔS(cal/K/mol) for each nearest-neighbor step.
+    fn step(x: u8, y: u8) -> Option<(f64, f64)> {
+        let params = match (x, y) {
+            (b'A', b'A') | (b'T', b'T') => (-7.9, -22.2),
+            (b'A', b'T') => (-7.2, -20.4),
+            (b'T', b'A') => (-7.2, -21.3),
+            (b'C', b'A') | (b'T', b'G') => (-8.5, -22.7),
+            (b'G', b'T') | (b'A', b'C') => (-8.4, -22.4),
+            (b'C', b'T') | (b'A', b'G') => (-7.8, -21.0),
+            (b'G', b'A') | (b'T', b'C') => (-8.2, -22.2),
+            (b'C', b'G') => (-10.6, -27.2),
+            (b'G', b'C') => (-9.8, -24.4),
+            (b'G', b'G') | (b'C', b'C') => (-8.0, -19.9),
+            _ => return None,
+        };
+        Some(params)
+    }
+    const GAS_CONSTANT: f64 = 1.987;
+    const SODIUM: f64 = 0.05;
+    const STRAND_CONC: f64 = 25e-9;
+    let primer = primer.to_ascii_uppercase();
+    if primer.is_empty() {
+        return 0.;
+    }
+    let (mut dh, mut ds) = (0., 0.);
+    for &end in [primer[0], primer[primer.len() - 1]].iter() {
+        match end {
+            b'A' | b'T' => {
+                dh += 2.3;
+                ds += 4.1;
+            }
+            _ => {
+                dh += 0.1;
+                ds -= 2.8;
+            }
+        }
+    }
+    for (h, s) in primer.windows(2).filter_map(|w| step(w[0], w[1])) {
+        dh += h;
+        ds += s;
+    }
+    ds += 0.368 * (primer.len() - 1) as f64 * SODIUM.ln();
+    let conc = if revcmp(&primer) == primer {
+        ds -= 1.4;
+        STRAND_CONC
+    } else {
+        STRAND_CONC / 2.
+    };
+    1000. * dh / (ds + GAS_CONSTANT * conc.ln()) - 273.15
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn it_works() {
+        assert_eq!(2 + 2, 4);
+    }
+    #[test]
+    fn revcmp_iupac() {
+        assert_eq!(revcmp(b"ACGT"), b"ACGT".to_vec());
+        assert_eq!(revcmp(b"AAcgN"), b"NcgTT".to_vec());
+        assert_eq!(revcmp(b"ARn-Tg"), b"cA-nYT".to_vec());
+        assert_eq!(revcmp(b"SWKMBVDH"), b"DHBVKMWS".to_vec());
+    }
+    #[test]
+    fn revcmp_rna_test() {
+        assert_eq!(revcmp_rna(b"AUGGCu"), b"aGCCAU".to_vec());
+        assert_eq!(revcmp_rna(&revcmp_rna(b"AUGCNuagc")), b"AUGCNuagc".to_vec());
+        assert_eq!(revcmp_rna(b"ACGT"), b"ACGU".to_vec());
+    }
+    #[test]
+    fn transcribe_test() {
+        assert_eq!(transcribe(b"ATGcNtT"), b"AUGcNuU".to_vec());
+        assert_eq!(reverse_transcribe(b"AUGcNuU"), b"ATGcNtT".to_vec());
+        assert_eq!(
+            reverse_transcribe(&transcribe(b"ACGTNacgtn")),
+            b"ACGTNacgtn".to_vec()
+        );
+    }
+    #[test]
+    fn canonical_kmer_test() {
+        // Palindromic k-mer.
+        assert_eq!(canonical_kmer(b"ACGT"), b"ACGT".to_vec());
+        assert_eq!(canonical_kmer(b"TTTG"), b"CAAA".to_vec());
+        assert_eq!(canonical_kmer(b"CAAA"), b"CAAA".to_vec());
+    }
+    #[test]
+    fn kmer_packing() {
+        let kmer = b"ACGTTGCAACGTAGCTAGCTTTACGATCGAAT";
+        let packed = kmer_to_u64(kmer).unwrap();
+        assert_eq!(u64_to_kmer(packed, kmer.len()), kmer.to_vec());
+        assert_eq!(kmer_to_u64(b"ACGT"), Some(0b00011011));
+        assert_eq!(
+            u64_to_kmer(kmer_to_u64(b"acgt").unwrap(), 4),
+            b"ACGT".to_vec()
+        );
+        assert_eq!(kmer_to_u64(b"ACNT"), None);
+        assert_eq!(kmer_to_u64(&[b'A'; 33]), None);
+    }
+    #[test]
+    fn validation_test() {
+        assert!(is_valid_dna(b"ACGTNacgtn"));
+        assert_eq!(first_invalid_base(b"ACGTNacgtn"), None);
+        assert!(!is_valid_dna(b"ACGRT"));
+        assert_eq!(first_invalid_base(b"ACGRT"), Some((3, b'R')));
+        assert_eq!(first_invalid_iupac_base(b"ACGRT"), None);
+        assert_eq!(first_invalid_iupac_base(b"AC-G\nT"), Some((4, b'\n')));
+    }
+    #[test]
+    fn pack_2bit_test() {
+        let seq = b"ACGTTGCAACGTAGCTAGCTTTACGATCGAATGCA";
+        assert_eq!(seq.len() % 4, 3);
+        let packed = pack_2bit(seq).unwrap();
+        assert_eq!(packed.len(), 9);
+        assert_eq!(packed[0], 0b00011011);
+        assert_eq!(unpack_2bit(&packed, seq.len()), seq.to_vec());
+        assert_eq!(pack_2bit(b"ACGTN"), None);
+        assert_eq!(pack_2bit(b""), Some(vec![]));
+        // The padding is unpacked as `A`s.
+        let mut padded = seq.to_vec();
+        padded.push(b'A');
+        assert_eq!(unpack_2bit(&packed, 4 * packed.len()), padded);
+        assert!(unpack_2bit(&[], 0).is_empty());
+    }
+    #[test]
+    #[should_panic]
+    fn unpack_2bit_overrun_test() {
+        let packed = pack_2bit(b"ACGTA").unwrap();
+        unpack_2bit(&packed, 9);
+    }
+    #[test]
+    fn composition_test() {
+        assert_eq!(base_composition(b"AACgtNN-"), [2, 1, 1, 1, 3]);
+        assert_eq!(gc_content(b"AACgtNN-"), 0.4);
+        assert_eq!(gc_content(b"NNN"), 0.);
+        let fasta = fasta::Record::with_data("test", &None, b"AACgtNN");
+        assert_eq!(fasta.gc_content(), gc_content(b"AACgtNN"));
+        assert_eq!(fasta.base_composition(), base_composition(b"AACgtNN"));
+        let fastq = fastq::Record::with_data("test", b"GGCAT", b"IIIII");
+        assert_eq!(fastq.gc_content(), 0.6);
+        assert_eq!(fastq.base_composition(), [1, 1, 2, 1, 0]);
+    }
+    #[test]
+    fn melting_temp_test() {
+        // Values from Biopython's `Tm_Wallace` and `Tm_NN` with the default parameters.
+        let primer = b"CGTTCCAAAGATGTGGGCATGAGCTTAC";
+        assert_eq!(melting_temp_wallace(primer), 84.);
+        assert!((melting_temp_nn(primer) - 60.32).abs() < 0.01);
+        assert!((melting_temp(primer) - 60.32).abs() < 0.01);
+        assert_eq!(melting_temp(b"ATGCATGC"), 24.);
+    }
+    #[test]
+    #[allow(deprecated)]
+    fn strand_conversion() {
+        // The deprecated aliases are the same type.
+        assert_eq!(lasttab::Strand::Forward, maf::Strand::Forward);
+        assert_eq!(Strand::from(true), Strand::Forward);
+        assert_eq!(Strand::from(false).reverse(), Strand::Forward);
+        let line = "q1\t100\t0\t100\t-\tt1\t200\t10\t110\t90\t100\t60";
+        let paf = paf::PAF::new(line).unwrap();
+        assert_eq!(paf.strand(), Strand::Reverse);
+        assert_eq!(paf.strand(), maf::Strand::Reverse);
+        assert_eq!(format!("{}", paf.strand()), "-");
+    }
+    #[test]
+    fn complement_test() {
+        assert_eq!(complement(b"AACCGGTT"), b"TTGGCCAA".to_vec());
+        assert_eq!(complement(b"acRn"), b"tgYn".to_vec());
+        assert_eq!(complement_base(b'g'), b'c');
+    }
+    #[test]
+    fn try_revcmp_test() {
+        assert_eq!(try_revcmp(b"ACGTn"), Ok(b"nACGT".to_vec()));
+        assert_eq!(try_revcmp(b"ACZGTQ"), Err(b'Z'));
+    }
+}
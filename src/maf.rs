@@ -13,6 +13,115 @@ pub fn parse_into_vec<P: AsRef<Path>>(path: P) -> std::io::Result<Vec<Record>> {
     Ok(reader.records().filter_map(|r| r.ok()).collect())
 }
 
+/// Concatenate the consecutive blocks whose rows of `reference` are adjacent, i.e., on the same strand
+/// and the next one starts within `max_gap` bases after the end of the previous one.
+/// The other species shared by the two blocks should be adjacent in the same way, otherwise the blocks are not stitched.
+/// The bases between the blocks are unknown, so they are filled with `N`s,
+/// each species in its own columns, and the other rows have gaps there.
+/// A species absent from one of the blocks has gaps in the columns of that block.
+/// The score of the stitched block is the sum of the scores, and the headers of the first block are kept.
+/// The blocks without `reference` are never stitched.
+pub fn stitch(blocks: &[Record], reference: &str, max_gap: u64) -> Vec<Record> {
+    let mut stitched: Vec<Record> = vec![];
+    for block in blocks {
+        match stitched.last_mut() {
+            Some(last) if is_adjacent(last, block, reference, max_gap) => {
+                *last = concatenate_blocks(last, block);
+            }
+            _ => stitched.push(block.clone()),
+        }
+    }
+    stitched
+}
+
+// The number of bases between `prev` and `next` if they can be concatenated.
+fn gap_between(prev: &Seq, next: &Seq, max_gap: u64) -> Option<u64> {
+    let end = prev.start + prev.length;
+    if prev.strand != next.strand || next.start < end || end + max_gap < next.start {
+        None
+    } else {
+        Some(next.start - end)
+    }
+}
+
+fn is_adjacent(prev: &Record, next: &Record, reference: &str, max_gap: u64) -> bool {
+    let (prev_ref, next_ref) = match (
+        prev.with_query_name(reference),
+        next.with_query_name(reference),
+    ) {
+        (Some(prev_ref), Some(next_ref)) => (prev_ref, next_ref),
+        _ => return false,
+    };
+    gap_between(prev_ref, next_ref, max_gap).is_some()
+        && prev
+            .sequence
+            .iter()
+            .all(|seq| match next.with_query_name(&seq.name) {
+                Some(next_seq) => gap_between(seq, next_seq, max_gap).is_some(),
+                None => true,
+            })
+}
+
+fn concatenate_blocks(prev: &Record, next: &Record) -> Record {
+    let mut names: Vec<&str> = prev.names();
+    for name in next.names() {
+        if !names.contains(&name) {
+            names.push(name);
+        }
+    }
+    let (prev_width, next_width) = (prev.alignment_length(), next.alignment_length());
+    let gaps: Vec<u64> = names
+        .iter()
+        .map(
+            |name| match (prev.with_query_name(name), next.with_query_name(name)) {
+                (Some(p), Some(n)) => n.start - p.start - p.length,
+                _ => 0,
+            },
+        )
+        .collect();
+    let sequence = names
+        .iter()
+        .enumerate()
+        .map(|(i, name)| {
+            let (p, n) = (prev.with_query_name(name), next.with_query_name(name));
+            let mut text = match p {
+                Some(p) => p.text.clone(),
+                None => vec![b'-'; prev_width],
+            };
+            for (j, &gap) in gaps.iter().enumerate() {
+                let fill = if i == j { b'N' } else { b'-' };
+                text.extend(std::iter::repeat_n(fill, gap as usize));
+            }
+            match n {
+                Some(n) => text.extend_from_slice(&n.text),
+                None => text.extend(std::iter::repeat_n(b'-', next_width)),
+            }
+            let first = p.or(n).unwrap();
+            let length =
+                p.map(|p| p.length).unwrap_or(0) + gaps[i] + n.map(|n| n.length).unwrap_or(0);
+            Seq {
+                name: name.to_string(),
+                start: first.start,
+                length,
+                strand: first.strand,
+                src_size: first.src_size,
+                text,
+            }
+        })
+        .collect::<Vec<_>>();
+    let score = match (prev.score, next.score) {
+        (Some(p), Some(n)) => Some(p + n),
+        _ => None,
+    };
+    Record {
+        score,
+        pass: None,
+        header: prev.header.clone(),
+        sequence_index: sequence.len(),
+        sequence,
+    }
+}
+
 /// A MAF reader.
 #[derive(Debug)]
 pub struct Reader<R: io::Read> {
@@ -59,6 +168,36 @@ impl<R: io::Read> Reader<R> {
     }
 }
 
+/// Error on a malformed MAF block.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MafError {
+    /// The alignment text of the sequence `name` has `length` columns,
+    /// while the first sequence has `expected` columns.
+    UnequalLength {
+        name: String,
+        length: usize,
+        expected: usize,
+    },
+}
+
+impl fmt::Display for MafError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MafError::UnequalLength {
+                name,
+                length,
+                expected,
+            } => write!(
+                f,
+                "the row of {} has {} columns, while {} columns are expected",
+                name, length, expected
+            ),
+        }
+    }
+}
+
+impl std::error::Error for MafError {}
+
 #[derive(Debug, Clone, Default)]
 pub struct Record {
     // Some field
@@ -98,7 +237,7 @@ pub struct Seq {
     name: String,
     start: u64,
     length: u64,
-    strand: Strand,
+    strand: crate::Strand,
     src_size: u64,
     text: Vec<u8>,
 }
@@ -125,9 +264,9 @@ impl Seq {
         self.start = seq[2].parse()?;
         self.length = seq[3].parse()?;
         self.strand = if seq[4] == "+" {
-            Strand::Forward
+            crate::Strand::Forward
         } else if seq[4] == "-" {
-            Strand::Reverse
+            crate::Strand::Reverse
         } else {
             unreachable!();
         };
@@ -144,14 +283,11 @@ impl Seq {
     pub fn length(&self) -> u64 {
         self.length
     }
-    pub fn strand(&self) -> Strand {
+    pub fn strand(&self) -> crate::Strand {
         self.strand
     }
     pub fn is_forward(&self) -> bool {
-        match self.strand {
-            Strand::Forward => true,
-            Strand::Reverse => false,
-        }
+        self.strand.is_forward()
     }
     pub fn src_size(&self) -> u64 {
         self.src_size
@@ -159,23 +295,18 @@ impl Seq {
     pub fn text(&self) -> &[u8] {
         &self.text
     }
-}
-
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
-pub enum Strand {
-    Forward,
-    Reverse,
-}
-
-impl fmt::Display for Strand {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        match self {
-            Strand::Forward => write!(f, "+")?,
-            Strand::Reverse => write!(f, "-")?,
-        };
-        Ok(())
+    // The start position on the forward strand.
+    fn start_from_forward(&self) -> u64 {
+        match self.strand {
+            crate::Strand::Forward => self.start,
+            crate::Strand::Reverse => self.src_size - self.start - self.length,
+        }
     }
 }
+
+/// Kept for compatibility. Use [`crate::Strand`] instead.
+#[deprecated(note = "use crate::Strand")]
+pub type Strand = crate::Strand;
 impl Record {
     pub fn is_empty(&self) -> bool {
         self.score.is_none()
@@ -203,6 +334,109 @@ impl Record {
     pub fn sequence(&self) -> &[Seq] {
         &self.sequence
     }
+    /// The number of sequences(rows) in this block.
+    pub fn num_sequences(&self) -> usize {
+        self.sequence.len()
+    }
+    /// The number of columns of this block, i.e., the length of the first row.
+    /// Use [`Record::validate`] to check all the rows have the same length.
+    pub fn alignment_length(&self) -> usize {
+        self.sequence.first().map(|seq| seq.text.len()).unwrap_or(0)
+    }
+    /// The names of the sequences, in the order of the rows.
+    pub fn names(&self) -> Vec<&str> {
+        self.sequence.iter().map(|seq| seq.name()).collect()
+    }
+    /// Check that all the rows in this block have the same number of columns.
+    pub fn validate(&self) -> Result<(), MafError> {
+        let expected = match self.sequence.first() {
+            Some(seq) => seq.text.len(),
+            None => return Ok(()),
+        };
+        match self.sequence.iter().find(|seq| seq.text.len() != expected) {
+            Some(seq) => Err(MafError::UnequalLength {
+                name: seq.name.clone(),
+                length: seq.text.len(),
+                expected,
+            }),
+            None => Ok(()),
+        }
+    }
+    /// Convert this block into pairwise alignments from the row named `reference`(the target)
+    /// to each other row(the query). The coordinates are on the forward strands, and
+    /// the strand is the relative one between the two rows. A column is counted in the `blocklen`
+    /// if either row has a base, and in the `matchnum` if both have the same base(case-insensitive).
+    /// The `mapq` is 255(missing). If there is no such reference, return an empty vector.
+    pub fn to_pafs(&self, reference: &str) -> Vec<crate::paf::PAF> {
+        let refr = match self.with_query_name(reference) {
+            Some(refr) => refr,
+            None => return vec![],
+        };
+        self.sequence
+            .iter()
+            .filter(|seq| !std::ptr::eq(*seq, refr))
+            .map(|seq| {
+                let columns = || refr.text.iter().zip(seq.text.iter());
+                let matchnum = columns()
+                    .filter(|(r, q)| **r != b'-' && r.eq_ignore_ascii_case(q))
+                    .count();
+                let blocklen = columns()
+                    .filter(|(r, q)| !(**r == b'-' && **q == b'-'))
+                    .count();
+                let (qstart, tstart) = (seq.start_from_forward(), refr.start_from_forward());
+                crate::paf::PAF {
+                    qname: seq.name.clone(),
+                    qlen: seq.src_size as usize,
+                    qstart: qstart as usize,
+                    qend: (qstart + seq.length) as usize,
+                    relstrand: seq.strand == refr.strand,
+                    tname: refr.name.clone(),
+                    tlen: refr.src_size as usize,
+                    tstart: tstart as usize,
+                    tend: (tstart + refr.length) as usize,
+                    matchnum,
+                    blocklen,
+                    mapq: 255,
+                    tags: vec![],
+                }
+            })
+            .collect()
+    }
+    /// Return the block on the opposite strands, i.e., every row is reverse complemented
+    /// and its start position is recomputed on the other strand by its `src_size`.
+    /// The score and the other headers are kept, and `revcmp` twice gives the original block.
+    pub fn revcmp(&self) -> Record {
+        let mut record = self.clone();
+        for seq in record.sequence.iter_mut() {
+            seq.start = seq.src_size - seq.start - seq.length;
+            seq.strand = seq.strand.reverse();
+            seq.text = crate::revcmp(&seq.text);
+        }
+        record
+    }
+    /// Move the first row named `reference` to the top, keeping the relative order of the other rows.
+    /// If there is no such row, the block is left unchanged.
+    pub fn reorder_by(&mut self, reference: &str) {
+        if let Some(idx) = self.sequence.iter().position(|seq| seq.name == reference) {
+            self.sequence[..=idx].rotate_right(1);
+        }
+    }
+    /// Return true if the block has a score and it is not less than `min`.
+    pub fn has_min_score(&self, min: f64) -> bool {
+        self.score.map(|score| min <= score).unwrap_or(false)
+    }
+    /// Return true if every species in `species` has a row in this block.
+    /// A row belongs to a species if its name is the species name itself or
+    /// starts with the species name followed by `.`, as in `hg38.chr1`.
+    pub fn contains_all(&self, species: &[&str]) -> bool {
+        species.iter().all(|species| {
+            self.sequence.iter().any(|seq| {
+                seq.name == *species
+                    || (seq.name.starts_with(species)
+                        && seq.name.as_bytes().get(species.len()) == Some(&b'.'))
+            })
+        })
+    }
     /// Return the sequence with specified name, if any.
     /// If there are multiple sequence with that name,
     /// one of the maches would be returned.
@@ -258,9 +492,9 @@ impl Record {
             let start: u64 = seq[2].parse()?;
             let length: u64 = seq[3].parse()?;
             let strand = if seq[4] == "+" {
-                Strand::Forward
+                crate::Strand::Forward
             } else if seq[4] == "-" {
-                Strand::Reverse
+                crate::Strand::Reverse
             } else {
                 unreachable!();
             };
@@ -295,6 +529,29 @@ impl<R: io::Read> Records<R> {
     }
 }
 
+impl<R: io::Read> Records<R> {
+    /// Keep only the blocks with scores not less than `min`. Errors are passed through.
+    /// See [`Record::has_min_score`].
+    pub fn min_score(self, min: f64) -> impl Iterator<Item = io::Result<Record>> {
+        self.filter(move |record| match record {
+            Ok(record) => record.has_min_score(min),
+            Err(_) => true,
+        })
+    }
+    /// Keep only the blocks containing all the species. Errors are passed through.
+    /// See [`Record::contains_all`].
+    pub fn contains_all(self, species: &[&str]) -> impl Iterator<Item = io::Result<Record>> {
+        let species: Vec<String> = species.iter().map(|s| s.to_string()).collect();
+        self.filter(move |record| match record {
+            Ok(record) => {
+                let species: Vec<&str> = species.iter().map(|s| s.as_str()).collect();
+                record.contains_all(&species)
+            }
+            Err(_) => true,
+        })
+    }
+}
+
 impl<R: io::Read> Iterator for Records<R> {
     type Item = io::Result<Record>;
     fn next(&mut self) -> Option<Self::Item> {
@@ -370,6 +627,159 @@ mod tests {
         }
     }
     #[test]
+    fn block_dimensions() {
+        let file: Vec<_> = Reader::from_file("./testdata/test.maf")
+            .unwrap()
+            .records()
+            .filter_map(|e| e.ok())
+            .collect();
+        assert_eq!(file[0].num_sequences(), 2);
+        assert_eq!(file[0].alignment_length(), 410);
+        assert_eq!(file[0].names(), vec!["Chr11", "Ctg0"]);
+        assert_eq!(file[1].alignment_length(), 49);
+        assert_eq!(Record::default().alignment_length(), 0);
+    }
+    #[test]
+    fn block_filters() {
+        let records = || Reader::from_file("./testdata/test.maf").unwrap().records();
+        let high: Vec<_> = records().min_score(260.).map(|r| r.unwrap()).collect();
+        assert_eq!(high.len(), 1);
+        assert_eq!(high[0].score(), Some(275.));
+        assert_eq!(records().min_score(251.).count(), 2);
+        assert_eq!(records().min_score(1000.).count(), 0);
+        assert_eq!(records().contains_all(&["Chr11", "Ctg0"]).count(), 2);
+        assert_eq!(records().contains_all(&["Chr11", "Ctg1"]).count(), 0);
+        assert_eq!(records().contains_all(&["Chr1"]).count(), 0);
+        let block: &[u8] = b"a score=10\ns hg38.chr1 0 4 + 10 ACGT\ns mm10.chr2 0 4 + 10 ACGT\n\n";
+        let record = Reader::new(block).records().next().unwrap().unwrap();
+        assert!(record.contains_all(&["hg38", "mm10.chr2"]));
+        assert!(!record.contains_all(&["hg3"]));
+        assert!(record.contains_all(&[]));
+        assert!(!Record::default().has_min_score(0.));
+    }
+    #[test]
+    fn revcmp_test() {
+        let records = parse_into_vec("./testdata/test.maf").unwrap();
+        let record = &records[0];
+        let reversed = record.revcmp();
+        assert_eq!(reversed.score(), record.score());
+        for (seq, rev) in record.sequence().iter().zip(reversed.sequence()) {
+            assert_eq!(rev.name(), seq.name());
+            assert_eq!(rev.strand(), seq.strand().reverse());
+            assert_eq!(rev.length(), seq.length());
+            assert_eq!(rev.start(), seq.src_size() - seq.start() - seq.length());
+            assert_eq!(rev.start_from_forward(), seq.start_from_forward());
+            assert_eq!(rev.text(), crate::revcmp(seq.text()).as_slice());
+        }
+        let refr = reversed.with_query_name("Chr11").unwrap();
+        assert_eq!(refr.start(), 38115440 - 1122118 - 385);
+        assert_eq!(reversed.revcmp().to_string(), record.to_string());
+    }
+    #[test]
+    fn reorder_by_test() {
+        let block: &[u8] = b"a score=10
+s sp1 0 4 + 10 ACGT
+s sp2 0 4 + 10 ACGA
+s ref 0 4 + 10 ACGC
+s sp3 0 4 + 10 ACGG
+
+";
+        let mut record = Reader::new(block).records().next().unwrap().unwrap();
+        record.reorder_by("ref");
+        assert_eq!(record.names(), vec!["ref", "sp1", "sp2", "sp3"]);
+        assert_eq!(record.sequence()[0].text(), b"ACGC");
+        record.reorder_by("ref");
+        assert_eq!(record.names(), vec!["ref", "sp1", "sp2", "sp3"]);
+        record.reorder_by("sp3");
+        assert_eq!(record.names(), vec!["sp3", "ref", "sp1", "sp2"]);
+        record.reorder_by("sp4");
+        assert_eq!(record.names(), vec!["sp3", "ref", "sp1", "sp2"]);
+    }
+    #[test]
+    fn stitch_test() {
+        let input = "a score=10
+s ref 100 5 + 1000 ACGTA
+s sp1  50 5 +  500 ACGTA
+
+a score=20
+s ref 107 4 + 1000 CC-GT
+s sp2  10 5 +  300 CCAGT
+s sp1  56 4 +  500 CCTG-
+
+a score=5
+s ref 500 3 + 1000 AAA
+s sp1 300 3 +  500 AAA
+
+";
+        let blocks: Vec<_> = Reader::new(input.as_bytes())
+            .records()
+            .map(|r| r.unwrap())
+            .collect();
+        assert_eq!(blocks.len(), 3);
+        let stitched = stitch(&blocks, "ref", 5);
+        assert_eq!(stitched.len(), 2);
+        let merged = &stitched[0];
+        assert!(merged.validate().is_ok());
+        assert_eq!(merged.score(), Some(30.));
+        assert_eq!(merged.names(), vec!["ref", "sp1", "sp2"]);
+        let rows: Vec<_> = merged
+            .sequence()
+            .iter()
+            .map(|seq| (seq.start(), seq.length(), seq.text().to_vec()))
+            .collect();
+        assert_eq!(rows[0], (100, 11, b"ACGTANN-CC-GT".to_vec()));
+        assert_eq!(rows[1], (50, 10, b"ACGTA--NCCTG-".to_vec()));
+        assert_eq!(rows[2], (10, 5, b"--------CCAGT".to_vec()));
+        assert_eq!(stitched[1].to_string(), blocks[2].to_string());
+        assert_eq!(stitch(&blocks, "ref", 1).len(), 3);
+        assert_eq!(stitch(&blocks, "sp2", 5).len(), 3);
+    }
+    #[test]
+    fn to_pafs_test() {
+        let file = parse_into_vec("./testdata/test.maf").unwrap();
+        let pafs = file[0].to_pafs("Chr11");
+        assert_eq!(pafs.len(), 1);
+        let paf = &pafs[0];
+        assert_eq!((paf.qname.as_str(), paf.tname.as_str()), ("Ctg0", "Chr11"));
+        assert_eq!(
+            (paf.qstart, paf.qend, paf.qlen),
+            (26518, 26518 + 385, 106115)
+        );
+        assert_eq!(
+            (paf.tstart, paf.tend, paf.tlen),
+            (1122118, 1122118 + 385, 38115440)
+        );
+        assert!(paf.relstrand);
+        assert_eq!((paf.matchnum, paf.blocklen), (291, 410));
+        assert_eq!(file[1].to_pafs("Ctg0")[0].matchnum, 49);
+        assert!(file[0].to_pafs("Chr1").is_empty());
+        let block: &[u8] = b"a score=10\ns ref 2 4 + 10 AC-GT\ns qry 1 5 - 8 ACTGA\n\n";
+        let record = Reader::new(block).records().next().unwrap().unwrap();
+        let paf = &record.to_pafs("ref")[0];
+        assert!(!paf.relstrand);
+        assert_eq!((paf.qstart, paf.qend), (2, 7));
+        assert_eq!((paf.matchnum, paf.blocklen), (3, 5));
+    }
+    #[test]
+    fn validate_test() {
+        let file: Vec<_> = Reader::from_file("./testdata/test.maf")
+            .unwrap()
+            .records()
+            .filter_map(|e| e.ok())
+            .collect();
+        assert!(file.iter().all(|record| record.validate().is_ok()));
+        let block: &[u8] = b"a score=10\ns seq1 0 4 + 10 ACGT\ns seq2 0 3 + 10 ACG\n\n";
+        let record = Reader::new(block).records().next().unwrap().unwrap();
+        assert_eq!(
+            record.validate(),
+            Err(MafError::UnequalLength {
+                name: "seq2".to_string(),
+                length: 3,
+                expected: 4
+            })
+        );
+    }
+    #[test]
     fn reuse_record() {
         // let mut answers: Vec<_> = Reader::from_file("./testdata/test.maf")
         //     .unwrap()
@@ -1,3 +1,4 @@
+use std::collections::HashMap;
 #[derive(Debug, Clone)]
 pub struct PAF {
     pub qname: String,
@@ -15,6 +16,19 @@ pub struct PAF {
     pub tags: Vec<(String, String, String)>,
 }
 
+/// A typed value of an optional tag. See [`PAF::tag_value`].
+#[derive(Debug, Clone, PartialEq)]
+pub enum PafTagValue {
+    /// Printable character, `A`.
+    A(char),
+    /// Integer, `i`.
+    I(i64),
+    /// Floating point number, `f`.
+    F(f64),
+    /// String, `Z`.
+    Z(String),
+}
+
 impl PAF {
     pub fn new(line: &str) -> Option<Self> {
         let mut line = line.split('\t');
@@ -33,7 +47,7 @@ impl PAF {
             mapq: line.next()?.parse().ok()?,
             tags: line
                 .filter_map(|tag| {
-                    let mut tag = tag.split(':').map(|x| x.to_string());
+                    let mut tag = tag.splitn(3, ':').map(|x| x.to_string());
                     let key = tag.next()?;
                     let type_str = tag.next()?;
                     let value = tag.next()?;
@@ -43,12 +57,63 @@ impl PAF {
         };
         Some(res)
     }
+    /// The relative strand as a [`crate::Strand`].
+    pub fn strand(&self) -> crate::Strand {
+        crate::Strand::from(self.relstrand)
+    }
     pub fn get_tag(&self, key: &str) -> Option<(&str, &str)> {
         self.tags
             .iter()
             .find(|(k, _, _)| key == k)
             .map(|(_, tag_type, value)| (tag_type.as_str(), value.as_str()))
     }
+    /// The value of the tag `key`, parsed according to its type. If the tag is absent, of an other type
+    /// such as `B`, or the value does not parse, return `None`. The raw `tags` are kept as they are.
+    pub fn tag_value(&self, key: &str) -> Option<PafTagValue> {
+        match self.get_tag(key)? {
+            ("A", value) => {
+                let mut chars = value.chars();
+                let c = chars.next()?;
+                chars.next().is_none().then_some(PafTagValue::A(c))
+            }
+            ("i", value) => value.parse().ok().map(PafTagValue::I),
+            ("f", value) => value.parse().ok().map(PafTagValue::F),
+            ("Z", value) => Some(PafTagValue::Z(value.to_string())),
+            _ => None,
+        }
+    }
+    /// The length of the aligned region on the query.
+    pub fn query_span(&self) -> usize {
+        self.qend - self.qstart
+    }
+    /// The length of the aligned region on the target.
+    pub fn target_span(&self) -> usize {
+        self.tend - self.tstart
+    }
+    /// Return true if the two alignments look like the signature of an inversion, i.e.,
+    /// they are between the same query and target, on the opposite strands, and
+    /// not overlapping on the query. The alignments are expected to be adjacent on the query.
+    pub fn is_inversion(&self, other: &PAF) -> bool {
+        self.qname == other.qname
+            && self.tname == other.tname
+            && self.relstrand != other.relstrand
+            && (self.qend <= other.qstart || other.qend <= self.qstart)
+    }
+    /// The gap-compressed per-base sequence divergence, given by the `de:f` tag.
+    pub fn divergence(&self) -> Option<f64> {
+        match self.get_tag("de")? {
+            ("f", value) => value.parse().ok(),
+            _ => None,
+        }
+    }
+    /// The type of the alignment, given by the `tp:A` tag.
+    /// `P`/`S`/`I` for primary/secondary/inversion, respectively.
+    pub fn alignment_type(&self) -> Option<char> {
+        match self.get_tag("tp")? {
+            ("A", value) if value.len() == 1 => value.chars().next(),
+            _ => None,
+        }
+    }
 }
 
 impl std::fmt::Display for PAF {
@@ -72,9 +137,7 @@ impl std::fmt::Display for PAF {
         let tags: String = tags
             .iter()
             .fold(String::new(), |mut tag, (key, val_type, value)| {
-                if !tag.is_empty() {
-                    tag.push('\t');
-                }
+                tag.push('\t');
                 tag += key;
                 tag.push(':');
                 tag += val_type;
@@ -84,7 +147,7 @@ impl std::fmt::Display for PAF {
             });
         write!(
             f,
-            "{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}",
+            "{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}{}",
             qname,
             qlen,
             qstart,
@@ -101,3 +164,458 @@ impl std::fmt::Display for PAF {
         )
     }
 }
+
+/// Write the records, one per line. The tags are written in the order they were parsed.
+pub fn write_all<W: std::io::Write>(records: &[PAF], w: &mut W) -> std::io::Result<()> {
+    for record in records {
+        writeln!(w, "{}", record)?;
+    }
+    Ok(())
+}
+
+/// Group the records by their query names. The order in each group is kept.
+pub fn group_by_query(records: Vec<PAF>) -> HashMap<String, Vec<PAF>> {
+    let mut groups: HashMap<_, Vec<_>> = HashMap::new();
+    for record in records {
+        groups.entry(record.qname.clone()).or_default().push(record);
+    }
+    groups
+}
+
+/// Keep the alignment with the largest `matchnum` for each query. See [`best_per_query_by_key`].
+pub fn best_per_query(records: Vec<PAF>) -> Vec<PAF> {
+    best_per_query_by_key(records, |record| record.matchnum)
+}
+
+/// Keep the alignment with the largest key for each query.
+/// If there are ties, the first one is kept. The result is sorted by the query names.
+pub fn best_per_query_by_key<K: Ord, F: FnMut(&PAF) -> K>(
+    records: Vec<PAF>,
+    mut key: F,
+) -> Vec<PAF> {
+    let mut bests: Vec<_> = group_by_query(records)
+        .into_values()
+        .filter_map(|group| {
+            let mut group = group.into_iter();
+            let first = group.next()?;
+            let first_key = key(&first);
+            let (best, _) = group.fold((first, first_key), |(best, best_key), record| {
+                let record_key = key(&record);
+                if best_key < record_key {
+                    (record, record_key)
+                } else {
+                    (best, best_key)
+                }
+            });
+            Some(best)
+        })
+        .collect();
+    bests.sort_by(|a, b| a.qname.cmp(&b.qname));
+    bests
+}
+
+/// An edge of the overlap graph, meaning that the suffix of `from` overlaps with the prefix of `to`.
+/// If `from_forward`(resp. `to_forward`) is false, the reverse complement of the read is meant.
+/// See [`to_overlap_edges`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct OverlapEdge {
+    pub from: String,
+    pub from_forward: bool,
+    pub to: String,
+    pub to_forward: bool,
+    /// The length of the overlap, i.e., the shorter one of the aligned spans.
+    pub overlap: usize,
+    /// The length of `from` before the overlap.
+    pub from_hang: usize,
+    /// The length of `to` after the overlap.
+    pub to_hang: usize,
+    /// The total length of the unaligned ends inside the overlap, which should be small for a true dovetail.
+    pub overhang: usize,
+    /// `matchnum / blocklen`.
+    pub identity: f64,
+}
+
+/// Convert all-vs-all alignments of reads into the edges of the overlap graph, as in miniasm.
+/// The alignments shorter than `min_overlap` or with the identity less than `min_identity` are discarded,
+/// and so are self alignments and the alignments where one read contains the other.
+/// Each of the remaining alignments is a dovetail overlap giving an edge, from the read whose
+/// unaligned end dangles out of the left end of the alignment(in the orientation of the query).
+/// Note that the internal matches, such as the ones caused by repeats, are not removed, but can be
+/// detected by their `overhang`.
+pub fn to_overlap_edges(
+    records: &[PAF],
+    min_overlap: usize,
+    min_identity: f64,
+) -> Vec<OverlapEdge> {
+    records
+        .iter()
+        .filter(|r| r.qname != r.tname && 0 < r.blocklen)
+        .filter_map(|r| {
+            let overlap = r.query_span().min(r.target_span());
+            let identity = r.matchnum as f64 / r.blocklen as f64;
+            if overlap < min_overlap || identity < min_identity {
+                return None;
+            }
+            // The coordinates on the target, in the orientation of the query.
+            let (tstart, tend) = match r.relstrand {
+                true => (r.tstart, r.tend),
+                false => (r.tlen - r.tend, r.tlen - r.tstart),
+            };
+            let (q_left, q_right) = (r.qstart, r.qlen - r.qend);
+            let (t_left, t_right) = (tstart, r.tlen - tend);
+            let overhang = q_left.min(t_left) + q_right.min(t_right);
+            if (q_left <= t_left && q_right <= t_right) || (t_left <= q_left && t_right <= q_right)
+            {
+                return None;
+            }
+            let query = (r.qname.clone(), true);
+            let target = (r.tname.clone(), r.relstrand);
+            let ((from, from_forward), (to, to_forward), from_hang, to_hang) = match t_left < q_left
+            {
+                true => (query, target, q_left - t_left, t_right - q_right),
+                false => (target, query, t_left - q_left, q_right - t_right),
+            };
+            Some(OverlapEdge {
+                from,
+                from_forward,
+                to,
+                to_forward,
+                overlap,
+                from_hang,
+                to_hang,
+                overhang,
+                identity,
+            })
+        })
+        .collect()
+}
+
+/// Convert each alignment into a segment of a dot plot, from `(qstart, tstart)` to `(qend, tend)` as `(query, target)` points.
+/// For the reverse strand, the target coordinate decreases along the query,
+/// so the segment is from `(qstart, tend)` to `(qend, tstart)`.
+pub fn to_dotplot_segments(records: &[PAF]) -> Vec<((usize, usize), (usize, usize))> {
+    records
+        .iter()
+        .map(|r| match r.relstrand {
+            true => ((r.qstart, r.tstart), (r.qend, r.tend)),
+            false => ((r.qstart, r.tend), (r.qend, r.tstart)),
+        })
+        .collect()
+}
+
+/// For each query, keep the primary alignments and the other ones whose scores are not less than
+/// `min_secondary_ratio` times the primary score, as minimap2's `-p` option. The score is the `matchnum`.
+/// The primary alignments are the ones with `tp:A:P`(see [`PAF::alignment_type`]), and the primary score is the best among them.
+/// If a query has no such tag, the alignment with the best score is regarded as the primary one instead.
+/// The order of the records is kept.
+pub fn filter_secondary(records: Vec<PAF>, min_secondary_ratio: f64) -> Vec<PAF> {
+    // The best score of the primary alignments, and that of all the alignments, for each query.
+    let mut bests: HashMap<String, (Option<usize>, usize)> = HashMap::new();
+    for record in records.iter() {
+        let best = bests.entry(record.qname.clone()).or_default();
+        if record.alignment_type() == Some('P') {
+            best.0 = Some(best.0.unwrap_or(0).max(record.matchnum));
+        }
+        best.1 = best.1.max(record.matchnum);
+    }
+    records
+        .into_iter()
+        .filter(|record| {
+            let (is_primary, primary_score) = match bests[&record.qname] {
+                (Some(primary), _) => (record.alignment_type() == Some('P'), primary),
+                (None, best) => (record.matchnum == best, best),
+            };
+            is_primary || min_secondary_ratio * primary_score as f64 <= record.matchnum as f64
+        })
+        .collect()
+}
+
+/// Merge collinear alignments between the same query and target on the same strand,
+/// such as the ones split by a large indel. Two alignments are collinear if the next one starts after the
+/// previous one ends both on the query and on the target(considering the strand), within `max_gap` bases.
+/// Every open chain is tried, so an unrelated fragment between two collinear ones, such as a repeat hit, does not break the chain.
+/// If more than one chain is collinear, the one ending nearest on the query is extended.
+/// The coordinates of the merged record are extended, the `matchnum`s and `blocklen`s are summed,
+/// the `mapq` is the maximum, and the tags are removed since they are no longer valid.
+/// The records are returned in the order of the first fragment of each chain.
+pub fn chain(records: &[PAF], max_gap: usize) -> Vec<PAF> {
+    let mut order: Vec<usize> = (0..records.len()).collect();
+    order.sort_by(|&i, &j| {
+        let (x, y) = (&records[i], &records[j]);
+        (&x.qname, &x.tname, x.relstrand, x.qstart).cmp(&(
+            &y.qname,
+            &y.tname,
+            y.relstrand,
+            y.qstart,
+        ))
+    });
+    let mut chains: Vec<(usize, PAF)> = vec![];
+    for i in order {
+        let next = &records[i];
+        // The chains between the same query and target on the same strand are at the tail, as the records are sorted.
+        // Among them, extend the collinear one ending nearest to the next fragment on the query.
+        let same_group = |(_, last): &&(usize, PAF)| {
+            last.qname == next.qname && last.tname == next.tname && last.relstrand == next.relstrand
+        };
+        let group_len = chains.iter().rev().take_while(same_group).count();
+        let group_start = chains.len() - group_len;
+        let nearest = chains[group_start..]
+            .iter_mut()
+            .filter(|(_, last)| is_collinear(last, next, max_gap))
+            .max_by_key(|(_, last)| last.qend);
+        match nearest {
+            Some((_, last)) => {
+                last.qend = last.qend.max(next.qend);
+                last.tstart = last.tstart.min(next.tstart);
+                last.tend = last.tend.max(next.tend);
+                last.matchnum += next.matchnum;
+                last.blocklen += next.blocklen;
+                last.mapq = last.mapq.max(next.mapq);
+                last.tags.clear();
+            }
+            None => chains.push((i, next.clone())),
+        }
+    }
+    chains.sort_by_key(|&(i, _)| i);
+    chains.into_iter().map(|(_, record)| record).collect()
+}
+
+fn is_collinear(prev: &PAF, next: &PAF, max_gap: usize) -> bool {
+    if prev.qname != next.qname || prev.tname != next.tname || prev.relstrand != next.relstrand {
+        return false;
+    }
+    let within = |start: usize, end: usize| end <= start && start - end <= max_gap;
+    let target = match prev.relstrand {
+        true => within(next.tstart, prev.tend),
+        false => within(prev.tstart, next.tend),
+    };
+    within(next.qstart, prev.qend) && target
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    const RECORDS: [&str; 4] = [
+        "read1\t1000\t0\t900\t+\tctg1\t5000\t100\t1000\t800\t900\t60\ttp:A:P",
+        "read1\t1000\t100\t1000\t-\tctg2\t5000\t0\t900\t850\t900\t0\ttp:A:S",
+        "read2\t500\t0\t500\t+\tctg1\t5000\t3000\t3500\t450\t500\t60\ttp:A:P",
+        "read1\t1000\t0\t300\t+\tctg3\t5000\t0\t300\t250\t300\t0\ttp:A:S",
+    ];
+    fn records() -> Vec<PAF> {
+        RECORDS.iter().map(|line| PAF::new(line).unwrap()).collect()
+    }
+    #[test]
+    fn round_trip() {
+        let input = std::fs::read_to_string("./testdata/test.paf").unwrap();
+        let records: Vec<_> = input.lines().map(|l| PAF::new(l).unwrap()).collect();
+        assert_eq!(records.len(), 4);
+        assert!(records[2].tags.is_empty());
+        assert_eq!(
+            records[3].get_tag("zd"),
+            Some(("Z", "key:value:with:colons"))
+        );
+        let keys: Vec<_> = records[0].tags.iter().map(|t| t.0.as_str()).collect();
+        assert_eq!(keys, vec!["tp", "cm", "s1", "s2", "NM", "de", "rl", "cg"]);
+        let mut output = vec![];
+        write_all(&records, &mut output).unwrap();
+        assert_eq!(String::from_utf8(output).unwrap(), input);
+    }
+    #[test]
+    fn group_by_query_test() {
+        let groups = group_by_query(records());
+        assert_eq!(groups.len(), 2);
+        let tnames: Vec<_> = groups["read1"].iter().map(|r| r.tname.as_str()).collect();
+        assert_eq!(tnames, vec!["ctg1", "ctg2", "ctg3"]);
+        assert_eq!(groups["read2"].len(), 1);
+    }
+    #[test]
+    fn typed_tags() {
+        let line = "read1\t1000\t0\t900\t+\tctg1\t5000\t100\t1000\t800\t900\t60\ttp:A:I\tcm:i:80\tde:f:0.0123";
+        let record = PAF::new(line).unwrap();
+        assert_eq!(record.divergence(), Some(0.0123));
+        assert_eq!(record.alignment_type(), Some('I'));
+        let record = &records()[0];
+        assert_eq!(record.alignment_type(), Some('P'));
+        assert_eq!(record.divergence(), None);
+        let line = "read1\t1000\t0\t900\t+\tctg1\t5000\t100\t1000\t800\t900\t60\ttp:Z:PS\tde:i:1";
+        let record = PAF::new(line).unwrap();
+        assert_eq!(record.alignment_type(), None);
+        assert_eq!(record.divergence(), None);
+    }
+    #[test]
+    fn tag_value_test() {
+        let input = std::fs::read_to_string("./testdata/test.paf").unwrap();
+        let records: Vec<_> = input.lines().map(|l| PAF::new(l).unwrap()).collect();
+        let record = &records[0];
+        assert_eq!(record.tag_value("NM"), Some(PafTagValue::I(100)));
+        assert_eq!(record.tag_value("de"), Some(PafTagValue::F(0.0123)));
+        assert_eq!(record.tag_value("tp"), Some(PafTagValue::A('P')));
+        let cigar = PafTagValue::Z("400M10I490M".to_string());
+        assert_eq!(record.tag_value("cg"), Some(cigar));
+        assert_eq!(record.tag_value("xx"), None);
+        let zd = PafTagValue::Z("key:value:with:colons".to_string());
+        assert_eq!(records[3].tag_value("zd"), Some(zd));
+        let line = "read1\t1000\t0\t900\t+\tctg1\t5000\t100\t1000\t800\t900\t60\tNM:i:x\ttp:A:PS\tSA:B:c,1";
+        let record = PAF::new(line).unwrap();
+        assert_eq!(record.tag_value("NM"), None);
+        assert_eq!(record.tag_value("tp"), None);
+        assert_eq!(record.tag_value("SA"), None);
+        assert_eq!(format!("{}", record), line);
+    }
+    #[test]
+    fn inversion_test() {
+        let lines = [
+            "read1\t3000\t0\t1000\t+\tctg1\t9000\t100\t1100\t950\t1000\t60",
+            "read1\t3000\t1000\t2000\t-\tctg1\t9000\t1100\t2050\t900\t1000\t60",
+            "read1\t3000\t2000\t3000\t+\tctg1\t9000\t2050\t3050\t900\t1000\t60",
+            "read1\t3000\t1500\t2500\t-\tctg2\t9000\t100\t1100\t900\t1000\t60",
+        ];
+        let records: Vec<_> = lines.iter().map(|l| PAF::new(l).unwrap()).collect();
+        assert_eq!(records[0].query_span(), 1000);
+        assert_eq!(records[1].target_span(), 950);
+        assert!(records[0].is_inversion(&records[1]));
+        assert!(records[1].is_inversion(&records[2]));
+        assert!(!records[0].is_inversion(&records[2]));
+        assert!(!records[0].is_inversion(&records[3]));
+        assert!(!records[1].is_inversion(&records[1]));
+    }
+    #[test]
+    fn overlap_edges_test() {
+        let records: Vec<_> = [
+            // Dovetail: the suffix of read1 overlaps with the prefix of read2.
+            "read1\t1000\t600\t1000\t+\tread2\t800\t0\t400\t380\t400\t60",
+            // Containment: read3 is inside read2.
+            "read3\t300\t0\t300\t+\tread2\t800\t200\t500\t290\t300\t60",
+            // Dovetail on the reverse strand: the suffix of the reverse complement of read4
+            // overlaps with the prefix of read1.
+            "read1\t1000\t0\t500\t-\tread4\t700\t0\t500\t480\t500\t60",
+            // Too low identity.
+            "read1\t1000\t600\t1000\t+\tread5\t800\t0\t400\t200\t400\t60",
+            "read1\t1000\t0\t1000\t+\tread1\t1000\t0\t1000\t1000\t1000\t60",
+        ]
+        .iter()
+        .map(|line| PAF::new(line).unwrap())
+        .collect();
+        let edges = to_overlap_edges(&records, 100, 0.9);
+        assert_eq!(edges.len(), 2);
+        let expected = OverlapEdge {
+            from: "read1".to_string(),
+            from_forward: true,
+            to: "read2".to_string(),
+            to_forward: true,
+            overlap: 400,
+            from_hang: 600,
+            to_hang: 400,
+            overhang: 0,
+            identity: 0.95,
+        };
+        assert_eq!(edges[0], expected);
+        let edge = &edges[1];
+        assert_eq!((edge.from.as_str(), edge.from_forward), ("read4", false));
+        assert_eq!((edge.to.as_str(), edge.to_forward), ("read1", true));
+        assert_eq!((edge.from_hang, edge.to_hang), (200, 500));
+        assert_eq!(to_overlap_edges(&records, 501, 0.0).len(), 0);
+    }
+    #[test]
+    fn dotplot_test() {
+        let segments = to_dotplot_segments(&records()[..2]);
+        assert_eq!(segments[0], ((0, 100), (900, 1000)));
+        assert_eq!(segments[1], ((100, 900), (1000, 0)));
+        let ((q1, t1), (q2, t2)) = segments[0];
+        assert!(q1 < q2 && t1 < t2);
+        let ((q1, t1), (q2, t2)) = segments[1];
+        assert!(q1 < q2 && t1 > t2);
+        assert!(to_dotplot_segments(&[]).is_empty());
+    }
+    #[test]
+    fn filter_secondary_test() {
+        // read1: 800(primary), 850, and 250. read2: 450.
+        let filtered = filter_secondary(records(), 0.8);
+        let kept: Vec<_> = filtered
+            .iter()
+            .map(|r| (r.qname.as_str(), r.matchnum))
+            .collect();
+        assert_eq!(kept, vec![("read1", 800), ("read1", 850), ("read2", 450)]);
+        let filtered = filter_secondary(records(), 1.0);
+        let kept: Vec<_> = filtered
+            .iter()
+            .map(|r| (r.qname.as_str(), r.matchnum, r.alignment_type()))
+            .collect();
+        let expected = vec![
+            ("read1", 800, Some('P')),
+            ("read1", 850, Some('S')),
+            ("read2", 450, Some('P')),
+        ];
+        assert_eq!(kept, expected);
+        let filtered = filter_secondary(records(), 1.1);
+        assert!(filtered.iter().all(|r| r.alignment_type() == Some('P')));
+        assert_eq!(filtered.len(), 2);
+        assert_eq!(filter_secondary(records(), 0.).len(), 4);
+        // Without the tp tag, the best alignment is kept.
+        let untagged: Vec<_> = RECORDS
+            .iter()
+            .map(|line| PAF::new(&line.replace("\ttp:A:P", "").replace("\ttp:A:S", "")).unwrap())
+            .collect();
+        let filtered = filter_secondary(untagged, 1.0);
+        let kept: Vec<_> = filtered.iter().map(|r| r.matchnum).collect();
+        assert_eq!(kept, vec![850, 450]);
+    }
+    #[test]
+    fn chain_test() {
+        let records: Vec<_> = [
+            "read1\t3000\t0\t1000\t+\tctg1\t9000\t100\t1100\t950\t1000\t60\tNM:i:50",
+            "read1\t3000\t1050\t2000\t+\tctg1\t9000\t1500\t2450\t900\t950\t60\tNM:i:50",
+            "read1\t3000\t2000\t3000\t+\tctg1\t9000\t100\t1100\t900\t1000\t10\tNM:i:100",
+            "read2\t3000\t0\t1000\t-\tctg1\t9000\t5000\t6000\t950\t1000\t60\tNM:i:50",
+            "read2\t3000\t1000\t2000\t-\tctg1\t9000\t3900\t4900\t950\t1000\t60\tNM:i:50",
+        ]
+        .iter()
+        .map(|line| PAF::new(line).unwrap())
+        .collect();
+        let chains = chain(&records, 500);
+        assert_eq!(chains.len(), 3);
+        let merged = &chains[0];
+        assert_eq!((merged.qstart, merged.qend), (0, 2000));
+        assert_eq!((merged.tstart, merged.tend), (100, 2450));
+        assert_eq!((merged.matchnum, merged.blocklen), (1850, 1950));
+        assert!(merged.tags.is_empty());
+        // Not collinear: the target goes backward.
+        assert_eq!(chains[1].qstart, 2000);
+        assert_eq!(chains[1].tags.len(), 1);
+        // Reverse strand: the target coordinates decrease.
+        assert_eq!((chains[2].qstart, chains[2].qend), (0, 2000));
+        assert_eq!((chains[2].tstart, chains[2].tend), (3900, 6000));
+        assert_eq!(chain(&records, 10).len(), 5);
+        // A repeat hit starting between two collinear pieces does not break the chain.
+        let records: Vec<_> = [
+            "read1\t3000\t0\t1000\t+\tctg1\t9000\t100\t1100\t950\t1000\t60",
+            "read1\t3000\t1020\t1500\t+\tctg1\t9000\t7000\t7480\t450\t480\t0",
+            "read1\t3000\t1050\t2000\t+\tctg1\t9000\t1150\t2100\t900\t950\t60",
+            "read1\t3000\t1500\t2500\t+\tctg1\t9000\t7500\t8500\t900\t1000\t0",
+        ]
+        .iter()
+        .map(|line| PAF::new(line).unwrap())
+        .collect();
+        let chains = chain(&records, 100);
+        assert_eq!(chains.len(), 2);
+        assert_eq!((chains[0].qstart, chains[0].qend), (0, 2000));
+        assert_eq!((chains[0].tstart, chains[0].tend), (100, 2100));
+        assert_eq!((chains[1].qstart, chains[1].qend), (1020, 2500));
+        assert_eq!((chains[1].tstart, chains[1].tend), (7000, 8500));
+    }
+    #[test]
+    fn best_per_query_test() {
+        let bests = best_per_query(records());
+        assert_eq!(bests.len(), 2);
+        assert_eq!(
+            (bests[0].qname.as_str(), bests[0].tname.as_str()),
+            ("read1", "ctg2")
+        );
+        assert_eq!(
+            (bests[1].qname.as_str(), bests[1].tname.as_str()),
+            ("read2", "ctg1")
+        );
+        let bests = best_per_query_by_key(records(), |r| r.mapq);
+        assert_eq!(bests[0].tname, "ctg1");
+    }
+}
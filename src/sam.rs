@@ -2,6 +2,11 @@
 use std::cmp::max;
 use std::io::BufRead;
 
+#[cfg(feature = "bam")]
+mod bam;
+#[cfg(feature = "bam")]
+pub use bam::{BamReader, BamRecords};
+
 /// Coverage on a contig.
 #[derive(Debug, Clone)]
 pub struct Coverage {
@@ -30,6 +35,65 @@ impl Coverage {
     pub fn new(records: &[Record]) -> Vec<Coverage> {
         records.iter().fold(vec![], combine_sam)
     }
+    /// Return the coverage as maximal runs `(start, end, depth)`, where
+    /// every position in `start..end` has the same depth.
+    /// Adjacent positions with the same depth are coalesced into one run,
+    /// while uncovered positions split runs.
+    pub fn runs(&self) -> Vec<(usize, usize, u64)> {
+        let mut runs: Vec<(usize, usize, u64)> = vec![];
+        for &(pos, depth) in self.cov.iter() {
+            match runs.last_mut() {
+                Some((_, end, d)) if *end == pos && *d == depth => *end += 1,
+                _ => runs.push((pos, pos + 1, depth)),
+            }
+        }
+        runs
+    }
+    /// Return the GC content and the mean depth of each window of `window` bases on the reference.
+    /// `refseq` should be the sequence of this contig. Note that
+    /// the positions of the coverage are 1-based, so the i-th window covers `refseq[i*window..(i+1)*window]`
+    /// and the positions from `i*window+1` to `(i+1)*window`. The last window may be shorter.
+    pub fn gc_bias(&self, refseq: &[u8], window: usize) -> Vec<(f64, f64)> {
+        if window == 0 {
+            return vec![];
+        }
+        let mut depths = vec![0; refseq.len().div_ceil(window)];
+        for &(pos, depth) in self.cov.iter().filter(|&&(pos, _)| 0 < pos) {
+            if let Some(total) = depths.get_mut((pos - 1) / window) {
+                *total += depth;
+            }
+        }
+        refseq
+            .chunks(window)
+            .zip(depths)
+            .map(|(seq, depth)| (crate::gc_content(seq), depth as f64 / seq.len() as f64))
+            .collect()
+    }
+    /// Pool the coverages from several inputs, such as one `Vec<Coverage>` per sample or file,
+    /// by summing the depths contig by contig. The result is sorted by the reference name, as [`Coverage::new`].
+    pub fn merge_all(covs: &[Vec<Coverage>]) -> Vec<Coverage> {
+        let mut contigs: std::collections::BTreeMap<&str, Vec<Coverage>> = Default::default();
+        for cov in covs.iter().flatten() {
+            contigs.entry(&cov.r_name).or_default().push(cov.clone());
+        }
+        contigs
+            .into_values()
+            .map(|mut group| {
+                // Merge pairwise in rounds, so that each position is merged O(log n) times.
+                while group.len() > 1 {
+                    group = group
+                        .chunks(2)
+                        .map(|pair| match pair {
+                            [x, y] => x.merge(y),
+                            [x] => x.clone(),
+                            _ => unreachable!(),
+                        })
+                        .collect();
+                }
+                group.pop().unwrap()
+            })
+            .collect()
+    }
     fn merge(&self, cov: &Self) -> Self {
         let mut res = Vec::with_capacity(max(self.cov.len(), cov.cov.len()));
         if self.r_name != cov.r_name {
@@ -65,24 +129,328 @@ pub struct Sam {
     pub headers: Vec<Header>,
     /// SAM records.
     pub records: Vec<Record>,
+    /// The number of the records and the longest reference span among them, recorded by [`Sam::sort_by_coordinate`].
+    max_span: Option<(usize, usize)>,
 }
 
 impl Sam {
-    /// Read SAM file from the BufferedReader.
-    pub fn from_reader<R: BufRead>(rdr: R) -> Sam {
+    pub fn new(headers: Vec<Header>, records: Vec<Record>) -> Self {
+        Self {
+            headers,
+            records,
+            max_span: None,
+        }
+    }
+    /// Read SAM file from the BufferedReader. Empty lines are skipped.
+    /// If a line is malformed, return an error of `InvalidData` kind with its line number(1-based).
+    pub fn from_reader<R: BufRead>(rdr: R) -> std::io::Result<Sam> {
         let mut headers = vec![];
         let mut records = vec![];
-        for line in rdr.lines().filter_map(|x| x.ok()) {
-            if line.starts_with('@') {
-                headers.push(Header::new(&line).unwrap());
+        for (idx, line) in rdr.lines().enumerate() {
+            let line = line?;
+            let invalid = |kind: &str| {
+                let message = format!("line {}: invalid SAM {}", idx + 1, kind);
+                std::io::Error::new(std::io::ErrorKind::InvalidData, message)
+            };
+            if line.is_empty() {
+                continue;
+            } else if line.starts_with('@') {
+                headers.push(Header::new(&line).ok_or_else(|| invalid("header"))?);
             } else {
-                records.push(line.parse::<Record>().unwrap());
+                records.push(line.parse::<Record>().map_err(|_| invalid("record"))?);
+            }
+        }
+        Ok(Self::new(headers, records))
+    }
+    /// Write the headers and then the records, one per line.
+    pub fn write<W: std::io::Write>(&self, w: &mut W) -> std::io::Result<()> {
+        for header in self.headers.iter() {
+            writeln!(w, "{}", header)?;
+        }
+        for record in self.records.iter() {
+            writeln!(w, "{}", record)?;
+        }
+        Ok(())
+    }
+    /// Sort the records by coordinate and set `SO:coordinate` in the `@HD` header.
+    /// The references are ordered as the `@SQ` headers, followed by the ones without `@SQ` in
+    /// lexicographical order. The unmapped records without reference come last.
+    /// If there is no `@HD` header, it is added with `VN:1.6`.
+    /// The longest reference span of the records is also recorded to speed up [`Sam::query_region`].
+    pub fn sort_by_coordinate(&mut self) {
+        let order = reference_order(&self.headers);
+        self.records.sort_by_cached_key(|r| {
+            let (unmapped, index, r_name) = reference_key(&order, &r.r_name);
+            (unmapped, index, r_name.to_string(), r.pos)
+        });
+        let max_span = self.records.iter().map(reference_span).max().unwrap_or(0);
+        self.max_span = Some((self.records.len(), max_span));
+        let sort_order = ("SO".to_string(), "coordinate".to_string());
+        match self.headers.iter_mut().find(|header| header.tag == "HD") {
+            Some(header) => match header.attrs.iter_mut().find(|(key, _)| key == "SO") {
+                Some(attr) => *attr = sort_order,
+                None => header.attrs.push(sort_order),
+            },
+            None => {
+                let header = Header {
+                    tag: "HD".to_string(),
+                    attrs: vec![("VN".to_string(), "1.6".to_string()), sort_order],
+                };
+                self.headers.insert(0, header);
+            }
+        }
+    }
+    /// Return the records overlapping with `[start, end)`(0-based) of the reference `ref_name`.
+    /// The records should be sorted as [`Sam::sort_by_coordinate`] does.
+    /// The block of the reference and the candidates in it are found by the binary search, and then
+    /// each candidate is checked by [`Record::refr_aligned_region`]. Unmapped records are never returned.
+    /// The first candidate is bounded by the longest reference span recorded by [`Sam::sort_by_coordinate`].
+    /// If it is not available, or the number of the records has changed since, the span is computed
+    /// over the block, which takes time linear to the records on the reference.
+    pub fn query_region(&self, ref_name: &str, start: usize, end: usize) -> Vec<&Record> {
+        let order = reference_order(&self.headers);
+        let target = reference_key(&order, ref_name);
+        let first = self
+            .records
+            .partition_point(|r| reference_key(&order, &r.r_name) < target);
+        let len =
+            self.records[first..].partition_point(|r| reference_key(&order, &r.r_name) <= target);
+        let block = &self.records[first..first + len];
+        let max_span = match self.max_span {
+            Some((num, max_span)) if num == self.records.len() => max_span,
+            _ => block.iter().map(reference_span).max().unwrap_or(0),
+        };
+        // A record at `pos`(1-based) ends at `pos - 1 + max_span`(0-based, exclusive) at the latest.
+        let lower = block.partition_point(|r| r.pos + max_span <= start + 1);
+        let upper = block.partition_point(|r| r.pos < end + 1);
+        block[lower..upper.max(lower)]
+            .iter()
+            .filter(|r| r.pos != 0)
+            .filter(|r| {
+                let (r_start, r_end) = r.refr_aligned_region();
+                r_start < end && start < r_end
+            })
+            .collect()
+    }
+    /// Return the supplementary alignments of the read `q_name`, i.e., the pieces of the split alignment
+    /// other than the primary one, in the order of the records.
+    /// To walk the split alignments of every read, use [`group_by_qname`] instead.
+    pub fn split_alignments(&self, q_name: &str) -> Vec<&Record> {
+        self.records
+            .iter()
+            .filter(|r| r.q_name == q_name && r.is_supplementary())
+            .collect()
+    }
+}
+
+/// The length of the aligned region of the record on the reference.
+fn reference_span(record: &Record) -> usize {
+    let (start, end) = record.refr_aligned_region();
+    end - start
+}
+
+/// The indices of the references in the `@SQ` headers.
+fn reference_order(headers: &[Header]) -> std::collections::HashMap<&str, usize> {
+    headers
+        .iter()
+        .filter(|header| header.tag == "SQ")
+        .filter_map(|header| header.attrs.iter().find(|(key, _)| key == "SN"))
+        .enumerate()
+        .map(|(i, (_, name))| (name.as_str(), i))
+        .collect()
+}
+
+/// The sort key of the reference `r_name` in [`Sam::sort_by_coordinate`]: the references in the `@SQ` headers
+/// in their order, the other ones in lexicographical order, and then `*`.
+fn reference_key<'a>(
+    order: &std::collections::HashMap<&str, usize>,
+    r_name: &'a str,
+) -> (bool, usize, &'a str) {
+    let index = order.get(r_name).copied().unwrap_or(usize::MAX);
+    (r_name == "*", index, r_name)
+}
+
+/// Group the records by their query names. In each group, the records are in the input order,
+/// so the primary alignment and its supplementary alignments can be combined into a split alignment.
+pub fn group_by_qname(records: &[Record]) -> std::collections::HashMap<&str, Vec<&Record>> {
+    let mut groups: std::collections::HashMap<_, Vec<_>> = std::collections::HashMap::new();
+    for record in records.iter() {
+        groups.entry(record.q_name()).or_default().push(record);
+    }
+    groups
+}
+
+/// Mark the PCR/optical duplicates by setting the 0x400 flag. The mapped primary alignments are duplicates
+/// if they share the reference, the position, the strand, and the CIGAR, and, for paired reads(0x1),
+/// the reference, the position, and the strand of the mate. In each set of duplicates, the one with the highest MAPQ
+/// (the first one in the input for ties) is the representative and its 0x400 flag is cleared. The other records are left as they are.
+pub fn mark_duplicates(records: &mut [Record]) {
+    let mut representatives: std::collections::HashMap<_, usize> = std::collections::HashMap::new();
+    let mut duplicates = vec![];
+    for (idx, record) in records.iter().enumerate() {
+        if record.flag & 0x4 == 0x4 || record.pos == 0 || !record.is_primary() {
+            continue;
+        }
+        let mate = (record.flag & 0x1 == 0x1)
+            .then(|| (record.mate_reference(), record.pnext, record.flag & 0x20));
+        let key = (
+            record.r_name.as_str(),
+            record.pos,
+            record.is_forward(),
+            record.cigar.as_str(),
+            mate,
+        );
+        match representatives.get_mut(&key) {
+            Some(best) if records[*best].mapq < record.mapq => {
+                duplicates.push(*best);
+                *best = idx;
+            }
+            Some(_) => duplicates.push(idx),
+            None => {
+                representatives.insert(key, idx);
             }
         }
-        Self { headers, records }
+    }
+    let representatives: Vec<_> = representatives.into_values().collect();
+    for idx in representatives {
+        records[idx].flag &= !0x400;
+    }
+    for idx in duplicates {
+        records[idx].flag |= 0x400;
+    }
+}
+
+/// Summary statistics of the read lengths. See [`read_length_stats`].
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct LengthStats {
+    /// The number of reads.
+    pub n: usize,
+    pub mean: f64,
+    /// The median. If the number of reads is even, the mean of the two middle lengths.
+    pub median: f64,
+    /// The length of the shortest read among the longest ones covering half of the total length.
+    pub n50: usize,
+}
+
+/// Compute the summary statistics of the read lengths, given by [`Record::query_length()`].
+/// Only the primary alignments are used, so that each read is counted once.
+/// For unmapped reads without CIGAR, the length of the SEQ is used instead.
+/// If there is no read, every value is zero.
+pub fn read_length_stats(records: &[Record]) -> LengthStats {
+    let mut lengths: Vec<_> = records
+        .iter()
+        .filter(|r| r.is_primary())
+        .map(|r| match (r.query_length(), r.seq()) {
+            (0, "*") => 0,
+            (0, seq) => seq.len(),
+            (len, _) => len,
+        })
+        .collect();
+    if lengths.is_empty() {
+        return LengthStats::default();
+    }
+    lengths.sort_unstable();
+    let n = lengths.len();
+    let total: usize = lengths.iter().sum();
+    let mean = total as f64 / n as f64;
+    let median = match n % 2 {
+        0 => (lengths[n / 2 - 1] + lengths[n / 2]) as f64 / 2.,
+        _ => lengths[n / 2] as f64,
+    };
+    let mut acc = 0;
+    let n50 = lengths
+        .iter()
+        .rev()
+        .find(|&&len| {
+            acc += len;
+            total <= 2 * acc
+        })
+        .copied()
+        .unwrap_or(0);
+    LengthStats {
+        n,
+        mean,
+        median,
+        n50,
     }
 }
 
+/// Collect the absolute template lengths of the properly paired(0x2) primary alignments.
+/// Only the leftmost mate, whose TLEN is positive, is counted, so that each pair is counted once.
+pub fn insert_sizes(records: &[Record]) -> Vec<usize> {
+    records
+        .iter()
+        .filter(|r| r.is_primary() && r.flag & 0x2 == 0x2 && 0 < r.tlen)
+        .map(|r| r.tlen.unsigned_abs() as usize)
+        .collect()
+}
+
+/// Summary statistics of the insert sizes. See [`insert_size_stats`].
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct InsertSizeStats {
+    /// The number of pairs.
+    pub n: usize,
+    pub mean: f64,
+    /// The median. If the number of pairs is even, the mean of the two middle sizes.
+    pub median: f64,
+    /// The standard deviation(not the unbiased one).
+    pub sd: f64,
+}
+
+/// Compute the summary statistics of [`insert_sizes`]. If there is no pair, every value is zero.
+pub fn insert_size_stats(records: &[Record]) -> InsertSizeStats {
+    let mut sizes = insert_sizes(records);
+    if sizes.is_empty() {
+        return InsertSizeStats::default();
+    }
+    sizes.sort_unstable();
+    let n = sizes.len();
+    let mean = sizes.iter().sum::<usize>() as f64 / n as f64;
+    let median = match n % 2 {
+        0 => (sizes[n / 2 - 1] + sizes[n / 2]) as f64 / 2.,
+        _ => sizes[n / 2] as f64,
+    };
+    let variance = sizes
+        .iter()
+        .map(|&size| (size as f64 - mean).powi(2))
+        .sum::<f64>()
+        / n as f64;
+    InsertSizeStats {
+        n,
+        mean,
+        median,
+        sd: variance.sqrt(),
+    }
+}
+
+/// Return the identities of the primary alignments(see [`Record::identity`]) and
+/// the number of the primary alignments skipped because they lack the NM or MD tag.
+/// Unmapped records are ignored.
+pub fn identity_distribution(records: &[Record]) -> (Vec<f64>, usize) {
+    let mut skipped = 0;
+    let identities = records
+        .iter()
+        .filter(|r| r.is_primary() && r.flag & 0x4 == 0)
+        .filter_map(|r| {
+            let identity = r.identity();
+            skipped += identity.is_none() as usize;
+            identity
+        })
+        .collect();
+    (identities, skipped)
+}
+
+/// The mean of the per-read error rates, `1 - identity`, over [`identity_distribution`].
+/// Return `None` if no record has an identity.
+pub fn mean_error_rate(records: &[Record]) -> Option<f64> {
+    let (identities, _) = identity_distribution(records);
+    if identities.is_empty() {
+        return None;
+    }
+    let total: f64 = identities.iter().map(|identity| 1. - identity).sum();
+    Some(total / identities.len() as f64)
+}
+
 /// SAM header file.
 #[derive(Debug, Clone)]
 pub struct Header {
@@ -90,6 +458,7 @@ pub struct Header {
     pub tag: String,
     /// Attributes for this tag. Each attribute is separated by '\t',
     /// and in "AttributeName:AttributeValue" format.
+    /// A field without ':', such as the text of `@CO`, is kept with an empty name.
     pub attrs: Vec<(String, String)>,
 }
 
@@ -98,17 +467,41 @@ impl Header {
         let mut line = line.split('\t');
         let tag: String = line.next()?.trim_start_matches('@').to_string();
         let attrs: Vec<_> = line
-            .filter_map(|attr| {
-                let mut attr = attr.splitn(2, ':');
-                let key = attr.next()?.to_string();
-                let value = attr.next()?.to_string();
-                Some((key, value))
+            .map(|attr| match attr.split_once(':') {
+                Some((key, value)) if tag != "CO" => (key.to_string(), value.to_string()),
+                _ => (String::new(), attr.to_string()),
             })
             .collect();
         Some(Self { tag, attrs })
     }
 }
 
+impl fmt::Display for Header {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "@{}", self.tag)?;
+        for (key, value) in self.attrs.iter() {
+            if key.is_empty() {
+                write!(f, "\t{}", value)?;
+            } else {
+                write!(f, "\t{}:{}", key, value)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// An entry of the `SA` tag, i.e., another piece of the split alignment. See [`Record::sa_tag`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SaEntry {
+    pub rname: String,
+    /// The mapped position(1-based).
+    pub pos: usize,
+    pub strand: crate::Strand,
+    pub cigar: Vec<Op>,
+    pub mapq: usize,
+    pub nm: usize,
+}
+
 /// SAM Record. The files can be accessed via method calling, such as [`Record::q_name()`].
 /// Since this struct implements [`std::str::FromStr`], it is possible to `let sam_record:Sam = line.parse().unwrap();` to parse the record.
 #[derive(Debug, Clone)]
@@ -121,7 +514,7 @@ pub struct Record {
     cigar: String,
     rnext: String,
     pnext: usize,
-    tlen: usize,
+    tlen: i64,
     seq: String,
     qual: Vec<u8>,
     attr: Vec<String>,
@@ -156,6 +549,14 @@ impl fmt::Display for Record {
 #[derive(Debug, Clone, Copy)]
 pub struct ParseSamError {}
 
+impl fmt::Display for ParseSamError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "invalid SAM record")
+    }
+}
+
+impl std::error::Error for ParseSamError {}
+
 impl std::str::FromStr for Record {
     type Err = ParseSamError;
     fn from_str(input: &str) -> Result<Self, Self::Err> {
@@ -177,8 +578,7 @@ impl std::str::FromStr for Record {
         let tlen = map(contents.next().and_then(|x| x.parse().ok()))?;
         let seq = map(contents.next())?.to_string();
         let qual = map(contents.next())?.bytes().map(|e| e - 33).collect();
-        let attr = map(contents.next().map(|e| e.to_string()))?;
-        let attr: Vec<_> = attr.split('\t').map(|x| x.to_string()).collect();
+        let attr: Vec<_> = contents.map(|x| x.to_string()).collect();
         Ok(Self {
             q_name,
             flag,
@@ -200,9 +600,9 @@ impl Record {
     pub fn to_coverage(&self) -> Coverage {
         let mut cov = vec![];
         let mut start = self.pos; // reference position
-        for op in &self.cigar() {
+        for op in self.cigar_iter() {
             use self::Op::*;
-            match *op {
+            match op {
                 Align(b) | Match(b) => {
                     for i in 0..b {
                         cov.push((start + i, 1));
@@ -233,12 +633,35 @@ impl Record {
     pub fn is_primary(&self) -> bool {
         (self.flag & 0x900) == 0
     }
+    /// Return true if this is a supplementary alignment(0x800), i.e., a piece of a split alignment.
+    pub fn is_supplementary(&self) -> bool {
+        (self.flag & 0x800) == 0x800
+    }
     pub fn is_template(&self) -> bool {
         (self.flag & 0b10000) != 0b10000
     }
     pub fn is_forward(&self) -> bool {
         (self.flag & 0b10000) != 0b10000
     }
+    /// Reference name of the mate/next read. `=` if it is the same as [`Record::r_name()`].
+    pub fn rnext(&self) -> &str {
+        &self.rnext
+    }
+    /// Reference name of the mate/next read, with `=` resolved to [`Record::r_name()`].
+    pub fn mate_reference(&self) -> &str {
+        match self.rnext.as_str() {
+            "=" => &self.r_name,
+            _ => &self.rnext,
+        }
+    }
+    /// Mapped position of the mate/next read (1-based).
+    pub fn pnext(&self) -> usize {
+        self.pnext
+    }
+    /// Observed template length. Negative if this is the rightmost segment.
+    pub fn tlen(&self) -> i64 {
+        self.tlen
+    }
     pub fn flag(&self) -> u32 {
         self.flag
     }
@@ -254,7 +677,7 @@ impl Record {
     pub fn query_aligned_region(&self) -> (usize, usize) {
         use self::Op::*; // 0-BASED!!!!!
         let (head_clip, middle, _tail_clip, _) =
-            self.cigar().iter().fold((0, 0, 0, true), |acc, x| match x {
+            self.cigar_iter().fold((0, 0, 0, true), |acc, x| match x {
                 HardClip(b) | SoftClip(b) if acc.3 => (acc.0 + b, acc.1, acc.2, acc.3),
                 HardClip(b) | SoftClip(b) if !acc.3 => (acc.0, acc.1, acc.2 + b, acc.3),
                 Align(b) | Insertion(b) | Match(b) | Mismatch(b) => {
@@ -274,9 +697,8 @@ impl Record {
         };
         use self::Op::*;
         let len: usize = self
-            .cigar()
-            .iter()
-            .map(|op| match *op {
+            .cigar_iter()
+            .map(|op| match op {
                 Align(b) | Match(b) | Deletion(b) | Skipped(b) | Mismatch(b) => b,
                 Insertion(_) | SoftClip(_) | HardClip(_) | Padding(_) => 0,
             })
@@ -285,23 +707,220 @@ impl Record {
     }
     /// Return the length of the query.
     pub fn query_length(&self) -> usize {
-        self.cigar()
-            .iter()
+        self.cigar_iter()
             .map(|e| match e {
                 Op::HardClip(b)
                 | Op::SoftClip(b)
                 | Op::Align(b)
                 | Op::Match(b)
                 | Op::Mismatch(b)
-                | Op::Insertion(b) => *b,
+                | Op::Insertion(b) => b,
                 _ => 0,
             })
             .sum()
     }
+    /// Return the leading and trailing soft-clipped bases, taken from the SEQ field.
+    /// If there is no soft clip or the SEQ is `*`, the corresponding vector is empty.
+    pub fn soft_clipped_seq(&self) -> (Vec<u8>, Vec<u8>) {
+        if self.seq == "*" {
+            return (vec![], vec![]);
+        }
+        let cigar = self.cigar();
+        fn soft_clip<'a, I: Iterator<Item = &'a Op>>(mut ops: I) -> usize {
+            match ops.find(|op| !matches!(op, Op::HardClip(_))) {
+                Some(Op::SoftClip(b)) => *b,
+                _ => 0,
+            }
+        }
+        let head = soft_clip(cigar.iter());
+        let tail = soft_clip(cigar.iter().rev());
+        let seq = self.seq.as_bytes();
+        if seq.len() < head + tail {
+            return (vec![], vec![]);
+        }
+        (seq[..head].to_vec(), seq[seq.len() - tail..].to_vec())
+    }
+    /// Return whether the read is clipped(soft or hard, summed up) by more than `min` bases at the left and the right end
+    /// of the alignment, respectively. Large clips are the signatures of chimeric reads and structural variants.
+    pub fn is_clipped(&self, min: usize) -> (bool, bool) {
+        let (head_clip, aligned_end) = self.query_aligned_region();
+        let tail_clip = self.query_length() - aligned_end;
+        (min < head_clip, min < tail_clip)
+    }
     /// Parse and return the Cigar string.
     /// This method takes `O(|L|)`-time, where `L` is the length of the Cigar string.
     pub fn cigar(&self) -> Vec<Op> {
-        parse_cigar_string(&self.cigar)
+        self.cigar_iter().collect()
+    }
+    /// Iterate over the Cigar operations, parsing the Cigar string lazily.
+    /// Unlike [`Record::cigar`], this does not allocate a vector.
+    pub fn cigar_iter(&self) -> impl Iterator<Item = Op> + '_ {
+        cigar_ops(&self.cigar)
+    }
+    /// Iterate over the aligned pairs `(reference position, query position)`, both 0-based, in the order of the Cigar string.
+    /// The query position is an index of the SEQ field, i.e., soft clipped bases are counted but hard clipped ones are not.
+    /// The gapped side is `None`: insertions and soft clips have no reference position,
+    /// and deletions and skipped regions have no query position.
+    pub fn aligned_pairs(&self) -> impl Iterator<Item = (Option<usize>, Option<usize>)> {
+        use self::Op::*;
+        let mut refr = self.pos.saturating_sub(1);
+        let mut query = 0;
+        self.cigar().into_iter().flat_map(move |op| {
+            let (len, on_refr, on_query) = match op {
+                Align(b) | Match(b) | Mismatch(b) => (b, true, true),
+                Insertion(b) | SoftClip(b) => (b, false, true),
+                Deletion(b) | Skipped(b) => (b, true, false),
+                HardClip(_) | Padding(_) => (0, false, false),
+            };
+            let (r, q) = (refr, query);
+            if on_refr {
+                refr += len;
+            }
+            if on_query {
+                query += len;
+            }
+            (0..len).map(move |i| {
+                let r = if on_refr { Some(r + i) } else { None };
+                let q = if on_query { Some(q + i) } else { None };
+                (r, q)
+            })
+        })
+    }
+    /// Return the query position(0-based, counting soft clipped bases) aligned to the reference position `ref_pos`(0-based).
+    /// Return `None` if the position is deleted, skipped, or outside the alignment.
+    /// This is the single-point version of [`Record::aligned_pairs`], walking the CIGAR operation by operation.
+    pub fn query_pos_at_ref(&self, ref_pos: usize) -> Option<usize> {
+        use self::Op::*;
+        if self.pos == 0 || ref_pos + 1 < self.pos {
+            return None;
+        }
+        let (mut refr, mut query) = (self.pos - 1, 0);
+        for op in self.cigar_iter() {
+            match op {
+                Align(b) | Match(b) | Mismatch(b) if ref_pos < refr + b => {
+                    return Some(query + ref_pos - refr)
+                }
+                Align(b) | Match(b) | Mismatch(b) => {
+                    refr += b;
+                    query += b;
+                }
+                Deletion(b) | Skipped(b) if ref_pos < refr + b => return None,
+                Deletion(b) | Skipped(b) => refr += b,
+                Insertion(b) | SoftClip(b) => query += b,
+                HardClip(_) | Padding(_) => {}
+            }
+        }
+        None
+    }
+    /// Return the Phred quality of the base aligned to each reference position in `[start, end)`(0-based).
+    /// The positions deleted, skipped, or outside the alignment are `None`. So are all the positions if the QUAL is `*`.
+    pub fn base_qualities_at_ref(&self, start: usize, end: usize) -> Vec<Option<u8>> {
+        let mut qualities = vec![None; end.saturating_sub(start)];
+        if self.qual == [b'*' - 33] || self.pos == 0 {
+            return qualities;
+        }
+        for (refr, query) in self.aligned_pairs() {
+            match (refr, query) {
+                (Some(refr), Some(query)) if start <= refr && refr < end => {
+                    qualities[refr - start] = self.qual.get(query).copied();
+                }
+                (Some(refr), _) if end <= refr => break,
+                _ => {}
+            }
+        }
+        qualities
+    }
+    /// Return the record clipped to the reference window `[start, end)`(0-based).
+    /// The aligned bases outside the window are converted into soft clips, so the SEQ and QUAL are kept,
+    /// and the POS is moved to the first aligned base in the window. The NM and MD tags are removed
+    /// since they are no longer valid. If the alignment has no aligned base in the window, return `None`.
+    pub fn trim_to_ref(&self, start: usize, end: usize) -> Option<Record> {
+        use self::Op::*;
+        let (r_start, r_end) = self.refr_aligned_region();
+        let (start, end) = (start.max(r_start), end.min(r_end));
+        if self.pos == 0 || end <= start {
+            return None;
+        }
+        let cigar = self.cigar();
+        let is_clip = |op: &&Op| matches!(op, HardClip(_) | SoftClip(_));
+        let head_clips = cigar.iter().take_while(is_clip).count();
+        let tail_clips = cigar[head_clips..].iter().rev().take_while(is_clip).count();
+        let core = &cigar[head_clips..cigar.len() - tail_clips];
+        let (head, rest) = split_cigar_at_ref(core, start - r_start);
+        let (mut middle, tail) = split_cigar_at_ref(&rest, end - start);
+        let query_len = |ops: &[Op]| -> usize {
+            ops.iter()
+                .map(|op| match *op {
+                    Align(l) | Match(l) | Mismatch(l) | Insertion(l) | SoftClip(l) => l,
+                    _ => 0,
+                })
+                .sum()
+        };
+        let (mut head_soft, mut tail_soft) = (query_len(&head), query_len(&tail));
+        let mut pos = start + 1;
+        while let Some(&op) = middle.first() {
+            match op {
+                Insertion(l) => head_soft += l,
+                Deletion(l) | Skipped(l) => pos += l,
+                _ => break,
+            }
+            middle.remove(0);
+        }
+        while let Some(&op) = middle.last() {
+            match op {
+                Insertion(l) => tail_soft += l,
+                Deletion(_) | Skipped(_) => {}
+                _ => break,
+            }
+            middle.pop();
+        }
+        if middle.is_empty() {
+            return None;
+        }
+        let (mut head_hard, mut tail_hard) = (vec![], vec![]);
+        for op in cigar[..head_clips].iter() {
+            match *op {
+                SoftClip(l) => head_soft += l,
+                op => head_hard.push(op),
+            }
+        }
+        for op in cigar[cigar.len() - tail_clips..].iter() {
+            match *op {
+                SoftClip(l) => tail_soft += l,
+                op => tail_hard.push(op),
+            }
+        }
+        let head_soft = Some(SoftClip(head_soft)).filter(|_| 0 < head_soft);
+        let tail_soft = Some(SoftClip(tail_soft)).filter(|_| 0 < tail_soft);
+        let ops = head_hard
+            .into_iter()
+            .chain(head_soft)
+            .chain(middle)
+            .chain(tail_soft)
+            .chain(tail_hard);
+        let mut record = self.clone();
+        record.cigar = ops.map(|op| op.as_str()).collect();
+        record.pos = pos;
+        record
+            .attr
+            .retain(|attr| !attr.starts_with("NM:") && !attr.starts_with("MD:"));
+        Some(record)
+    }
+    /// Return the SEQ aligned column-for-column to the reference region given by [`Record::refr_aligned_region`].
+    /// The deleted and skipped bases are filled with `-`, and the inserted and clipped bases are removed.
+    /// If the SEQ is `*`, return an empty vector. Bases beyond the end of a too short SEQ are filled with `N`.
+    pub fn aligned_sequence(&self) -> Vec<u8> {
+        if self.seq == "*" {
+            return vec![];
+        }
+        let seq = self.seq.as_bytes();
+        self.aligned_pairs()
+            .filter(|(refr, _)| refr.is_some())
+            .map(|(_, query)| match query {
+                Some(q) => seq.get(q).copied().unwrap_or(b'N'),
+                None => b'-',
+            })
+            .collect()
     }
     fn cigar_as_str(&self) -> &str {
         &self.cigar
@@ -313,6 +932,117 @@ impl Record {
     pub fn attr(&self) -> &[String] {
         self.attr.as_slice()
     }
+    /// Return the type and the value of the tag, if present.
+    pub fn get_tag(&self, key: &str) -> Option<(&str, &str)> {
+        self.attr.iter().find_map(|attr| {
+            let mut attr = attr.splitn(3, ':');
+            if attr.next()? != key {
+                return None;
+            }
+            Some((attr.next()?, attr.next()?))
+        })
+    }
+    /// Parse the `SA:Z:` tag, which is a list of `rname,pos,strand,CIGAR,mapQ,NM;`.
+    /// Return `None` if the tag is absent or malformed.
+    pub fn sa_tag(&self) -> Option<Vec<SaEntry>> {
+        let (_, value) = self.get_tag("SA")?;
+        value
+            .split(';')
+            .filter(|entry| !entry.is_empty())
+            .map(|entry| {
+                let mut fields = entry.split(',');
+                let rname = fields.next()?.to_string();
+                let pos = fields.next()?.parse().ok()?;
+                let strand = match fields.next()? {
+                    "+" => crate::Strand::Forward,
+                    "-" => crate::Strand::Reverse,
+                    _ => return None,
+                };
+                let cigar = parse_cigar_string(fields.next()?);
+                let mapq = fields.next()?.parse().ok()?;
+                let nm = fields.next()?.parse().ok()?;
+                Some(SaEntry {
+                    rname,
+                    pos,
+                    strand,
+                    cigar,
+                    mapq,
+                    nm,
+                })
+            })
+            .collect()
+    }
+    /// The identity of the alignment, i.e., `1 - NM / L`, where `L` is the number of the alignment
+    /// columns(`M`/`=`/`X`/`I`/`D`). If the NM tag is absent, the edit distance is computed from
+    /// the MD tag and the CIGAR. Return `None` if neither tag is available or there is no column.
+    pub fn identity(&self) -> Option<f64> {
+        let cigar = self.cigar();
+        let columns: usize = cigar
+            .iter()
+            .map(|op| match *op {
+                Op::Align(l)
+                | Op::Match(l)
+                | Op::Mismatch(l)
+                | Op::Insertion(l)
+                | Op::Deletion(l) => l,
+                _ => 0,
+            })
+            .sum();
+        if columns == 0 {
+            return None;
+        }
+        let nm = match self.get_tag("NM") {
+            Some(("i", nm)) => nm.parse::<usize>().ok()?,
+            _ => self
+                .cigar_with_mismatches()?
+                .iter()
+                .map(|op| match *op {
+                    Op::Mismatch(l) | Op::Insertion(l) | Op::Deletion(l) => l,
+                    _ => 0,
+                })
+                .sum(),
+        };
+        Some(crate::alignments::cigar_identity(&cigar, nm))
+    }
+    /// Return the CIGAR with each `M` resolved into `=` and `X` by the MD tag.
+    /// If the MD tag is absent or inconsistent with the CIGAR, return `None`.
+    pub fn cigar_with_mismatches(&self) -> Option<Vec<Op>> {
+        let mut md = match self.get_tag("MD")? {
+            ("Z", md) => parse_md(md)?,
+            _ => return None,
+        };
+        let mut ops: Vec<Op> = vec![];
+        for op in self.cigar_iter() {
+            match op {
+                Op::Align(mut len) | Op::Match(mut len) | Op::Mismatch(mut len) => {
+                    while len > 0 {
+                        let (op, consumed) = match md.pop_front()? {
+                            MdOp::Match(n) if len < n => {
+                                md.push_front(MdOp::Match(n - len));
+                                (Op::Match(len), len)
+                            }
+                            MdOp::Match(n) => (Op::Match(n), n),
+                            MdOp::Mismatch => (Op::Mismatch(1), 1),
+                            MdOp::Deletion(_) => return None,
+                        };
+                        len -= consumed;
+                        match (ops.last_mut(), op) {
+                            (Some(Op::Match(l)), Op::Match(m))
+                            | (Some(Op::Mismatch(l)), Op::Mismatch(m)) => *l += m,
+                            _ => ops.push(op),
+                        }
+                    }
+                }
+                Op::Deletion(len) => match md.pop_front()? {
+                    MdOp::Deletion(n) if n == len => ops.push(op),
+                    _ => return None,
+                },
+                _ => ops.push(op),
+            }
+        }
+        // The MD tag should not describe more bases than the CIGAR.
+        md.is_empty().then_some(ops)
+    }
 }
 
 /// Alignment operations. Insertions are insertions to the reference, and deletions are deletions from the reference.
@@ -379,21 +1109,94 @@ impl Op {
     }
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum MdOp {
+    Match(usize),
+    Mismatch,
+    Deletion(usize),
+}
+
+// Parse the MD tag such as `10A5^AC0T6`. The zero-length matches are removed.
+fn parse_md(md: &str) -> Option<std::collections::VecDeque<MdOp>> {
+    let mut ops = std::collections::VecDeque::new();
+    let mut md = md.as_bytes();
+    while !md.is_empty() {
+        let digits = md.iter().take_while(|b| b.is_ascii_digit()).count();
+        if 0 < digits {
+            let num = std::str::from_utf8(&md[..digits]).ok()?.parse().ok()?;
+            if 0 < num {
+                ops.push_back(MdOp::Match(num));
+            }
+            md = &md[digits..];
+        } else if md[0] == b'^' {
+            let len = md[1..]
+                .iter()
+                .take_while(|b| b.is_ascii_alphabetic())
+                .count();
+            if len == 0 {
+                return None;
+            }
+            ops.push_back(MdOp::Deletion(len));
+            md = &md[len + 1..];
+        } else if md[0].is_ascii_alphabetic() {
+            ops.push_back(MdOp::Mismatch);
+            md = &md[1..];
+        } else {
+            return None;
+        }
+    }
+    Some(ops)
+}
+
+// Split the operations at `offset` bases from the start on the reference.
+// An operation across the boundary is split into two, and the insertions at the boundary go to the latter.
+fn split_cigar_at_ref(ops: &[Op], offset: usize) -> (Vec<Op>, Vec<Op>) {
+    use self::Op::*;
+    let (mut head, mut tail) = (vec![], vec![]);
+    let mut refr = 0;
+    for &op in ops {
+        let (len, resize): (usize, fn(usize) -> Op) = match op {
+            Align(l) => (l, Align),
+            Match(l) => (l, Match),
+            Mismatch(l) => (l, Mismatch),
+            Deletion(l) => (l, Deletion),
+            Skipped(l) => (l, Skipped),
+            _ => (0, |_| unreachable!()),
+        };
+        if offset <= refr {
+            tail.push(op);
+        } else if refr + len <= offset {
+            head.push(op);
+        } else {
+            head.push(resize(offset - refr));
+            tail.push(resize(refr + len - offset));
+        }
+        refr += len;
+    }
+    (head, tail)
+}
+
 /// Parse a given CIGAR string. If it is not a valid CIGAR, panic.
 pub fn parse_cigar_string(cigar: &str) -> Vec<Op> {
-    let mut ops = vec![];
-    let mut num = 0;
-    for x in cigar.bytes() {
-        if x.is_ascii_digit() {
-            num = 10 * num + (x - b'0') as usize;
-        } else {
-            if let Some(res) = Op::from(num, x) {
-                ops.push(res);
+    cigar_ops(cigar).collect()
+}
+
+// Parse the CIGAR string lazily. Unknown operations are skipped.
+fn cigar_ops(cigar: &str) -> impl Iterator<Item = Op> + '_ {
+    let mut bytes = cigar.bytes();
+    std::iter::from_fn(move || {
+        let mut num = 0;
+        for x in bytes.by_ref() {
+            if x.is_ascii_digit() {
+                num = 10 * num + (x - b'0') as usize;
+            } else if let Some(op) = Op::from(num, x) {
+                return Some(op);
+            } else {
+                num = 0;
             }
-            num = 0;
         }
-    }
-    ops
+        None
+    })
 }
 
 #[test]
@@ -407,3 +1210,583 @@ fn cigar_parse() {
         vec![SoftClip(101), Align(33), Insertion(2), Align(66)]
     );
 }
+
+#[test]
+fn mate_information() {
+    let line = "read1\t99\tchr1\t100\t60\t50M\t=\t300\t250\t*\t*\tNM:i:0";
+    let record: Record = line.parse().unwrap();
+    assert_eq!(record.rnext(), "=");
+    assert_eq!(record.mate_reference(), "chr1");
+    assert_eq!(record.pnext(), 300);
+    assert_eq!(record.tlen(), 250);
+    let line = "read1\t147\tchr1\t300\t60\t50M\tchr2\t100\t-250\t*\t*\tNM:i:0";
+    let record: Record = line.parse().unwrap();
+    assert_eq!(record.mate_reference(), "chr2");
+    assert_eq!(record.tlen(), -250);
+    assert_eq!(format!("{}", record), line);
+}
+
+#[test]
+fn from_reader_malformed() {
+    let sam = "@HD\tVN:1.6\tSO:unsorted
+read1\t0\tchr1\t100\t60\t4M\t*\t0\t0\tACGT\tIIII\tNM:i:0
+read2\t0\tchr1\tNOT_A_NUMBER\t60\t4M\t*\t0\t0\tACGT\tIIII\tNM:i:0
+";
+    let error = Sam::from_reader(sam.as_bytes()).unwrap_err();
+    assert_eq!(error.kind(), std::io::ErrorKind::InvalidData);
+    assert!(error.to_string().contains("line 3"), "{}", error);
+    let sam = "@HD\tVN:1.6\tSO:unsorted
+read1\t0\tchr1\t100\t60\t4M\t*\t0\t0\tACGT\tIIII\tNM:i:0
+
+";
+    let sam = Sam::from_reader(sam.as_bytes()).unwrap();
+    assert_eq!(sam.headers.len(), 1);
+    assert_eq!(sam.records.len(), 1);
+}
+
+#[test]
+fn gc_bias_test() {
+    let records: Vec<Record> = [
+        "read1\t0\tchr1\t1\t60\t4M\t*\t0\t0\tACGT\tIIII\tNM:i:0",
+        "read2\t0\tchr1\t3\t60\t6M\t*\t0\t0\tGTAAAA\tIIIIII\tNM:i:0",
+    ]
+    .iter()
+    .map(|line| line.parse().unwrap())
+    .collect();
+    let coverage = Coverage::new(&records);
+    let refseq = b"ACGTAAAAGG";
+    let bias = coverage[0].gc_bias(refseq, 4);
+    // Depths: 1,1,2,2,1,1,1,1,0,0
+    assert_eq!(bias, vec![(0.5, 1.5), (0., 1.), (1., 0.)]);
+}
+
+#[test]
+fn coverage_runs() {
+    let records: Vec<Record> = [
+        "read1\t0\tchr1\t1\t60\t4M\t*\t0\t0\tACGT\tIIII\tNM:i:0",
+        "read2\t0\tchr1\t3\t60\t2M\t*\t0\t0\tGT\tII\tNM:i:0",
+        "read3\t0\tchr1\t5\t60\t2M\t*\t0\t0\tAA\tII\tNM:i:0",
+        "read4\t0\tchr1\t10\t60\t2M3D2M\t*\t0\t0\tACGT\tIIII\tNM:i:3",
+    ]
+    .iter()
+    .map(|line| line.parse().unwrap())
+    .collect();
+    let coverage = &Coverage::new(&records)[0];
+    let runs = coverage.runs();
+    assert_eq!(
+        runs,
+        vec![(1, 3, 1), (3, 5, 2), (5, 7, 1), (10, 12, 1), (15, 17, 1)]
+    );
+    let depths: Vec<_> = runs
+        .iter()
+        .flat_map(|&(start, end, depth)| (start..end).map(move |pos| (pos, depth)))
+        .collect();
+    assert_eq!(depths, coverage.cov());
+}
+
+#[test]
+fn soft_clipped_seq_test() {
+    let seq = format!("{}{}{}", "C".repeat(10), "A".repeat(50), "G".repeat(5));
+    let line = format!(
+        "read1\t0\tchr1\t100\t60\t10S50M5S\t*\t0\t0\t{}\t*\tNM:i:0",
+        seq
+    );
+    let record: Record = line.parse().unwrap();
+    let (head, tail) = record.soft_clipped_seq();
+    assert_eq!(head, vec![b'C'; 10]);
+    assert_eq!(tail, vec![b'G'; 5]);
+    let line = "read1\t0\tchr1\t100\t60\t2H4M\t*\t0\t0\tACGT\t*\tNM:i:0";
+    let record: Record = line.parse().unwrap();
+    assert_eq!(record.soft_clipped_seq(), (vec![], vec![]));
+    let line = "read1\t0\tchr1\t100\t60\t3H2S4M\t*\t0\t0\t*\t*\tNM:i:0";
+    let record: Record = line.parse().unwrap();
+    assert_eq!(record.soft_clipped_seq(), (vec![], vec![]));
+}
+
+#[test]
+fn is_clipped_test() {
+    let clipped = |cigar: &str, min: usize| {
+        let line = format!("read1\t0\tchr1\t100\t60\t{}\t*\t0\t0\t*\t*", cigar);
+        line.parse::<Record>().unwrap().is_clipped(min)
+    };
+    assert_eq!(clipped("30S70M", 20), (true, false));
+    assert_eq!(clipped("70M30S", 20), (false, true));
+    assert_eq!(clipped("20S60M21S", 20), (false, true));
+    assert_eq!(clipped("10H15S50M5S30H", 20), (true, true));
+    assert_eq!(clipped("100M", 0), (false, false));
+}
+
+#[test]
+fn read_length_stats_test() {
+    let records: Vec<Record> = [
+        "read1\t0\tchr1\t1\t60\t100M\t*\t0\t0\t*\t*\tNM:i:0",
+        "read2\t0\tchr1\t1\t60\t10S180M10H\t*\t0\t0\t*\t*\tNM:i:0",
+        "read2\t256\tchr2\t1\t60\t200M\t*\t0\t0\t*\t*\tNM:i:0",
+        "read3\t0\tchr1\t1\t60\t300M5I\t*\t0\t0\t*\t*\tNM:i:5",
+        "read4\t4\t*\t0\t0\t*\t*\t0\t0\tACGTACGTAC\t*\tNM:i:0",
+    ]
+    .iter()
+    .map(|line| line.parse().unwrap())
+    .collect();
+    // Lengths: 10, 100, 200, 305. The total is 615, 305 + 200 = 505 >= 307.5.
+    let stats = read_length_stats(&records);
+    assert_eq!(stats.n, 4);
+    assert_eq!(stats.mean, 615. / 4.);
+    assert_eq!(stats.median, 150.);
+    assert_eq!(stats.n50, 200);
+    assert_eq!(read_length_stats(&records[..1]).median, 100.);
+    assert_eq!(read_length_stats(&[]), LengthStats::default());
+}
+
+#[test]
+fn cigar_with_mismatches_test() {
+    use Op::*;
+    let line =
+        "read1\t0\tchr1\t100\t60\t2S10M2I5M3D6M\t*\t0\t0\t*\t*\tNM:i:7\tMD:Z:3G6A4^ACG0T5\tAS:i:10";
+    let record: Record = line.parse().unwrap();
+    assert_eq!(record.attr().len(), 3);
+    assert_eq!(record.get_tag("AS"), Some(("i", "10")));
+    let ops = record.cigar_with_mismatches().unwrap();
+    let expected = vec![
+        SoftClip(2),
+        Match(3),
+        Mismatch(1),
+        Match(6),
+        Insertion(2),
+        Mismatch(1),
+        Match(4),
+        Deletion(3),
+        Mismatch(1),
+        Match(5),
+    ];
+    assert_eq!(ops, expected);
+    let line = "read1\t0\tchr1\t100\t60\t10M\t*\t0\t0\t*\t*";
+    let record: Record = line.parse().unwrap();
+    assert!(record.attr().is_empty());
+    assert!(record.cigar_with_mismatches().is_none());
+    let line = "read1\t0\tchr1\t100\t60\t10M\t*\t0\t0\t*\t*\tMD:Z:5^A4";
+    let record: Record = line.parse().unwrap();
+    assert!(record.cigar_with_mismatches().is_none());
+    // MD left over after the CIGAR.
+    for md in ["20", "10A5", "10^A"] {
+        let line = format!("read1\t0\tchr1\t100\t60\t10M\t*\t0\t0\t*\t*\tMD:Z:{}", md);
+        let record: Record = line.parse().unwrap();
+        assert!(record.cigar_with_mismatches().is_none(), "{}", md);
+    }
+    let line = "read1\t0\tchr1\t100\t60\t10M\t*\t0\t0\t*\t*\tMD:Z:9A0";
+    let record: Record = line.parse().unwrap();
+    assert_eq!(
+        record.cigar_with_mismatches(),
+        Some(vec![Match(9), Mismatch(1)])
+    );
+}
+
+#[test]
+fn query_region_test() {
+    let sam = "@SQ\tSN:chr1\tLN:1000
+@SQ\tSN:chr2\tLN:1000
+read1\t0\tchr1\t1\t60\t100M\t*\t0\t0\t*\t*
+read2\t0\tchr1\t50\t60\t300M\t*\t0\t0\t*\t*
+read3\t0\tchr1\t150\t60\t10M\t*\t0\t0\t*\t*
+read4\t0\tchr1\t200\t60\t10M\t*\t0\t0\t*\t*
+read5\t0\tchr2\t150\t60\t10M\t*\t0\t0\t*\t*
+read6\t4\t*\t0\t0\t*\t*\t0\t0\tACGT\t*
+";
+    let sam = Sam::from_reader(sam.as_bytes()).unwrap();
+    let names = |records: Vec<&Record>| -> Vec<String> {
+        records.iter().map(|r| r.q_name().to_string()).collect()
+    };
+    // read1 covers [0, 100), read2 [49, 349), read3 [149, 159), read4 [199, 209).
+    assert_eq!(
+        names(sam.query_region("chr1", 100, 199)),
+        vec!["read2", "read3"]
+    );
+    assert_eq!(
+        names(sam.query_region("chr1", 99, 199)),
+        vec!["read1", "read2", "read3"]
+    );
+    assert_eq!(
+        names(sam.query_region("chr1", 208, 209)),
+        vec!["read2", "read4"]
+    );
+    assert_eq!(names(sam.query_region("chr2", 0, 1000)), vec!["read5"]);
+    assert!(sam.query_region("chr3", 0, 1000).is_empty());
+    assert!(sam.query_region("chr1", 400, 1000).is_empty());
+    // The references are in the order of the @SQ headers, not in lexicographical order.
+    let sam = "@SQ\tSN:chr2\tLN:1000
+@SQ\tSN:chr10\tLN:1000
+read1\t0\tchr10\t1\t60\t10M\t*\t0\t0\t*\t*
+read2\t0\tchrUn\t1\t60\t10M\t*\t0\t0\t*\t*
+read3\t0\tchr2\t5\t60\t10M\t*\t0\t0\t*\t*
+read4\t0\tchr2\t1\t60\t10M\t*\t0\t0\t*\t*
+read5\t4\t*\t0\t0\t*\t*\t0\t0\tACGT\t*
+";
+    let mut sam = Sam::from_reader(sam.as_bytes()).unwrap();
+    sam.sort_by_coordinate();
+    assert_eq!(
+        names(sam.query_region("chr2", 0, 100)),
+        vec!["read4", "read3"]
+    );
+    assert_eq!(names(sam.query_region("chr10", 0, 100)), vec!["read1"]);
+    assert_eq!(names(sam.query_region("chrUn", 0, 100)), vec!["read2"]);
+    assert!(sam.query_region("*", 0, 100).is_empty());
+    // The first candidate is bounded by the longest span, here, of read1.
+    let sam = "@SQ\tSN:chr1\tLN:10000
+read1\t0\tchr1\t1\t60\t900M\t*\t0\t0\t*\t*
+read2\t0\tchr1\t10\t60\t10M\t*\t0\t0\t*\t*
+read3\t0\tchr1\t850\t60\t10M\t*\t0\t0\t*\t*
+read4\t0\tchr1\t2000\t60\t10M\t*\t0\t0\t*\t*
+";
+    let mut sam = Sam::from_reader(sam.as_bytes()).unwrap();
+    assert_eq!(
+        names(sam.query_region("chr1", 855, 901)),
+        vec!["read1", "read3"]
+    );
+    sam.sort_by_coordinate();
+    assert_eq!(sam.max_span, Some((4, 900)));
+    assert_eq!(
+        names(sam.query_region("chr1", 855, 901)),
+        vec!["read1", "read3"]
+    );
+    assert_eq!(
+        names(sam.query_region("chr1", 900, 1999)),
+        Vec::<String>::new()
+    );
+    assert_eq!(names(sam.query_region("chr1", 1500, 2001)), vec!["read4"]);
+    // A longer record added after sorting is still found, as the span is recomputed.
+    let line = "read5\t0\tchr1\t3000\t60\t5000M\t*\t0\t0\t*\t*";
+    sam.records.push(line.parse().unwrap());
+    assert_eq!(names(sam.query_region("chr1", 7000, 7001)), vec!["read5"]);
+}
+
+#[test]
+fn write_and_sort() {
+    let input = "@HD\tVN:1.6\tSO:unsorted
+@SQ\tSN:chr2\tLN:1000
+@SQ\tSN:chr1\tLN:1000
+@PG\tID:minimap2\tPN:minimap2\tVN:2.24\tCL:minimap2 -a ref.fa reads.fq
+@CO\tfree text: with a colon
+@CO\tno colon here
+read1\t0\tchr1\t100\t60\t4M\t*\t0\t0\tACGT\tIIII\tNM:i:0
+read2\t4\t*\t0\t0\t*\t*\t0\t0\tACGT\tIIII
+read3\t16\tchr2\t300\t60\t4M\t*\t0\t0\tACGT\tIIII\tNM:i:0\tAS:i:8
+read4\t0\tchr1\t50\t60\t4M\t*\t0\t0\tACGT\tIIII\tNM:i:0
+read5\t0\tchr2\t10\t60\t4M\t*\t0\t0\tACGT\tIIII\tNM:i:0
+";
+    let mut sam = Sam::from_reader(input.as_bytes()).unwrap();
+    let mut output = vec![];
+    sam.write(&mut output).unwrap();
+    assert_eq!(String::from_utf8(output).unwrap(), input);
+    sam.sort_by_coordinate();
+    let names: Vec<_> = sam.records.iter().map(|r| r.q_name()).collect();
+    assert_eq!(names, vec!["read5", "read3", "read4", "read1", "read2"]);
+    let mut output = vec![];
+    sam.write(&mut output).unwrap();
+    let output = String::from_utf8(output).unwrap();
+    assert!(output.starts_with("@HD\tVN:1.6\tSO:coordinate\n@SQ\tSN:chr2"));
+    let mut sam = Sam::from_reader(output.as_bytes()).unwrap();
+    assert_eq!(sam.records.len(), 5);
+    assert_eq!(
+        sam.headers[4].attrs,
+        vec![(String::new(), "free text: with a colon".to_string())]
+    );
+    sam.headers.clear();
+    sam.sort_by_coordinate();
+    assert_eq!(format!("{}", sam.headers[0]), "@HD\tVN:1.6\tSO:coordinate");
+}
+
+#[test]
+fn identity_distribution_test() {
+    let records: Vec<Record> = [
+        "read1\t0\tchr1\t1\t60\t90M10I\t*\t0\t0\t*\t*\tNM:i:20",
+        "read2\t0\tchr1\t1\t60\t5S45M5D\t*\t0\t0\t*\t*\tNM:i:5",
+        "read2\t256\tchr1\t1\t60\t50M\t*\t0\t0\t*\t*\tNM:i:50",
+        "read3\t0\tchr1\t1\t60\t10M\t*\t0\t0\t*\t*\tMD:Z:4A5",
+        "read4\t0\tchr1\t1\t60\t10M\t*\t0\t0\t*\t*",
+        "read5\t4\t*\t0\t0\t*\t*\t0\t0\tACGT\t*",
+    ]
+    .iter()
+    .map(|line| line.parse().unwrap())
+    .collect();
+    let (identities, skipped) = identity_distribution(&records);
+    assert_eq!(identities, vec![0.8, 0.9, 0.9]);
+    assert_eq!(skipped, 1);
+    let error = mean_error_rate(&records).unwrap();
+    assert!((error - 0.4 / 3.).abs() < 1e-10);
+    assert_eq!(mean_error_rate(&records[4..]), None);
+}
+
+#[test]
+fn aligned_pairs_test() {
+    let line = "read1\t0\tchr1\t11\t60\t1H2S2M1I2M2D1M\t*\t0\t0\tAACCGTTAC\t*\tNM:i:3";
+    let record: Record = line.parse().unwrap();
+    let pairs: Vec<_> = record.aligned_pairs().collect();
+    let expected = vec![
+        (None, Some(0)),
+        (None, Some(1)),
+        (Some(10), Some(2)),
+        (Some(11), Some(3)),
+        (None, Some(4)),
+        (Some(12), Some(5)),
+        (Some(13), Some(6)),
+        (Some(14), None),
+        (Some(15), None),
+        (Some(16), Some(7)),
+    ];
+    assert_eq!(pairs, expected);
+    let (start, end) = record.refr_aligned_region();
+    let refr: Vec<_> = pairs.iter().filter_map(|p| p.0).collect();
+    assert_eq!(refr, (start..end).collect::<Vec<_>>());
+    let query = pairs.iter().filter(|p| p.1.is_some()).count();
+    assert_eq!(query, record.seq().len() - 1);
+}
+
+#[test]
+fn split_alignments_test() {
+    let input = "@SQ\tSN:chr1\tLN:1000
+read1\t0\tchr1\t100\t60\t50M50S\t*\t0\t0\t*\t*\tSA:Z:chr1,500,+,50S50M,60,0;
+read2\t0\tchr1\t300\t60\t100M\t*\t0\t0\t*\t*
+read1\t2048\tchr1\t500\t60\t50H50M\t*\t0\t0\t*\t*\tSA:Z:chr1,100,+,50M50S,60,0;
+read2\t256\tchr1\t700\t0\t100M\t*\t0\t0\t*\t*
+";
+    let sam = Sam::from_reader(input.as_bytes()).unwrap();
+    let groups = group_by_qname(&sam.records);
+    assert_eq!(groups.len(), 2);
+    let read1 = &groups["read1"];
+    assert_eq!(read1.len(), 2);
+    assert!(read1[0].is_primary() && read1[1].is_supplementary());
+    assert_eq!(groups["read2"].len(), 2);
+    let split = sam.split_alignments("read1");
+    assert_eq!(split.len(), 1);
+    assert_eq!(split[0].pos(), 500);
+    assert!(sam.split_alignments("read2").is_empty());
+    assert!(sam.split_alignments("read3").is_empty());
+}
+
+#[test]
+fn sa_tag_test() {
+    let line = "read1\t0\tchr1\t100\t60\t50M50S\t*\t0\t0\t*\t*\tNM:i:0\tSA:Z:chr2,500,-,50S50M,30,2;chr1,900,+,60S40M,0,1;";
+    let record: Record = line.parse().unwrap();
+    let entries = record.sa_tag().unwrap();
+    assert_eq!(entries.len(), 2);
+    let expected = SaEntry {
+        rname: "chr2".to_string(),
+        pos: 500,
+        strand: crate::Strand::Reverse,
+        cigar: vec![Op::SoftClip(50), Op::Align(50)],
+        mapq: 30,
+        nm: 2,
+    };
+    assert_eq!(entries[0], expected);
+    assert_eq!(entries[1].rname, "chr1");
+    assert_eq!(entries[1].strand, crate::Strand::Forward);
+    assert_eq!(entries[1].cigar, parse_cigar_string("60S40M"));
+    assert_eq!(
+        (entries[1].pos, entries[1].mapq, entries[1].nm),
+        (900, 0, 1)
+    );
+    let line = "read1\t0\tchr1\t100\t60\t100M\t*\t0\t0\t*\t*\tNM:i:0";
+    let record: Record = line.parse().unwrap();
+    assert!(record.sa_tag().is_none());
+    let line = "read1\t0\tchr1\t100\t60\t100M\t*\t0\t0\t*\t*\tSA:Z:chr2,500,?,50M,30,2;";
+    let record: Record = line.parse().unwrap();
+    assert!(record.sa_tag().is_none());
+}
+
+#[test]
+fn aligned_sequence_test() {
+    let line = "read1\t0\tchr1\t3\t60\t2S3M2I2M1D3M\t*\t0\t0\tTTACGGGTAGCA\t*\tNM:i:3";
+    let record: Record = line.parse().unwrap();
+    let refr = b"NNACGTAAGCA";
+    let aligned = record.aligned_sequence();
+    assert_eq!(aligned, b"ACGTA-GCA".to_vec());
+    let (start, end) = record.refr_aligned_region();
+    assert_eq!(aligned.len(), end - start);
+    let line = "read1\t0\tchr1\t3\t60\t9M\t*\t0\t0\tACGTAAGCA\t*\tNM:i:0";
+    let record: Record = line.parse().unwrap();
+    assert_eq!(record.aligned_sequence(), refr[2..].to_vec());
+    let line = "read1\t0\tchr1\t3\t60\t9M\t*\t0\t0\t*\t*\tNM:i:0";
+    let record: Record = line.parse().unwrap();
+    assert!(record.aligned_sequence().is_empty());
+    let line = "read1\t0\tchr1\t3\t60\t3M1D4M\t*\t0\t0\tACGTA\t*\tNM:i:1";
+    let record: Record = line.parse().unwrap();
+    assert_eq!(record.aligned_sequence(), b"ACG-TANN".to_vec());
+}
+
+#[test]
+fn query_pos_at_ref_test() {
+    let line = "read1\t0\tchr1\t11\t60\t1H2S2M1I2M2D1M\t*\t0\t0\tAACCGTTAC\t*\tNM:i:3";
+    let record: Record = line.parse().unwrap();
+    assert_eq!(record.query_pos_at_ref(10), Some(2));
+    assert_eq!(record.query_pos_at_ref(12), Some(5));
+    assert_eq!(record.query_pos_at_ref(14), None);
+    assert_eq!(record.query_pos_at_ref(15), None);
+    assert_eq!(record.query_pos_at_ref(16), Some(7));
+    assert_eq!(record.query_pos_at_ref(9), None);
+    assert_eq!(record.query_pos_at_ref(17), None);
+    for (refr, query) in record
+        .aligned_pairs()
+        .filter_map(|(r, q)| r.map(|r| (r, q)))
+    {
+        assert_eq!(record.query_pos_at_ref(refr), query);
+    }
+}
+
+#[test]
+fn cigar_iter_test() {
+    let line = "read1\t0\tchr1\t11\t60\t5H10S20M3I2D15=1X4N6M7S\t*\t0\t0\t*\t*";
+    let record: Record = line.parse().unwrap();
+    let ops: Vec<_> = record.cigar_iter().collect();
+    assert_eq!(ops, record.cigar());
+    assert_eq!(ops.len(), 10);
+    assert_eq!(ops[0], Op::HardClip(5));
+    assert_eq!(ops[9], Op::SoftClip(7));
+    let line = "read1\t4\t*\t0\t0\t*\t*\t0\t0\tACGT\t*";
+    let record: Record = line.parse().unwrap();
+    assert_eq!(record.cigar_iter().count(), 0);
+}
+
+#[test]
+fn insert_size_test() {
+    let records: Vec<Record> = [
+        "pair1\t99\tchr1\t100\t60\t100M\t=\t300\t300\t*\t*",
+        "pair1\t147\tchr1\t300\t60\t100M\t=\t100\t-300\t*\t*",
+        "pair2\t163\tchr1\t500\t60\t100M\t=\t800\t400\t*\t*",
+        "pair2\t83\tchr1\t800\t60\t100M\t=\t500\t-400\t*\t*",
+        "pair3\t99\tchr1\t1000\t60\t100M\t=\t1250\t350\t*\t*",
+        "pair3\t147\tchr1\t1250\t60\t100M\t=\t1000\t-350\t*\t*",
+        // Not properly paired, and a secondary alignment.
+        "pair4\t97\tchr1\t2000\t60\t100M\tchr2\t100\t0\t*\t*",
+        "pair1\t355\tchr1\t5000\t0\t100M\t=\t300\t5000\t*\t*",
+    ]
+    .iter()
+    .map(|line| line.parse().unwrap())
+    .collect();
+    assert_eq!(insert_sizes(&records), vec![300, 400, 350]);
+    let stats = insert_size_stats(&records);
+    assert_eq!(stats.n, 3);
+    assert_eq!(stats.mean, 350.);
+    assert_eq!(stats.median, 350.);
+    assert!((stats.sd - (5000f64 / 3.).sqrt()).abs() < 1e-9);
+    assert_eq!(insert_size_stats(&records[6..]), InsertSizeStats::default());
+}
+
+#[test]
+fn trim_to_ref_test() {
+    let line = "read1\t0\tchr1\t101\t60\t100M\t*\t0\t0\t*\t*\tNM:i:0\tMD:Z:100\tAS:i:100";
+    let record: Record = line.parse().unwrap();
+    // The alignment covers [100, 200). Keep [130, 170).
+    let trimmed = record.trim_to_ref(130, 170).unwrap();
+    assert_eq!(trimmed.cigar(), parse_cigar_string("30S40M30S"));
+    assert_eq!(trimmed.pos(), 131);
+    assert_eq!(trimmed.refr_aligned_region(), (130, 170));
+    assert_eq!(trimmed.query_length(), 100);
+    assert_eq!(trimmed.attr(), &["AS:i:100".to_string()]);
+    let trimmed = record.trim_to_ref(0, 120).unwrap();
+    assert_eq!(trimmed.cigar(), parse_cigar_string("20M80S"));
+    assert_eq!(trimmed.pos(), 101);
+    assert!(record.trim_to_ref(200, 300).is_none());
+    let line = "read1\t0\tchr1\t101\t60\t5H10S20M5I10M3D20M5S\t*\t0\t0\t*\t*";
+    let record: Record = line.parse().unwrap();
+    // The deletion is at [130, 133), and the insertion is just before 120.
+    let trimmed = record.trim_to_ref(120, 131).unwrap();
+    assert_eq!(trimmed.cigar(), parse_cigar_string("5H35S10M25S"));
+    assert_eq!(trimmed.pos(), 121);
+    let trimmed = record.trim_to_ref(131, 140).unwrap();
+    assert_eq!(trimmed.cigar(), parse_cigar_string("5H45S7M18S"));
+    assert_eq!(trimmed.pos(), 134);
+    assert!(record.trim_to_ref(130, 133).is_none());
+    let (head, tail) = split_cigar_at_ref(&parse_cigar_string("20M5I10M"), 20);
+    assert_eq!(
+        (head, tail),
+        (vec![Op::Align(20)], parse_cigar_string("5I10M"))
+    );
+}
+
+#[test]
+fn base_qualities_at_ref_test() {
+    let line = "read1\t0\tchr1\t11\t60\t2S3M1I2M2D2M\t*\t0\t0\tTTACGGTAGC\t!!ABCDEFGH\tNM:i:3";
+    let record: Record = line.parse().unwrap();
+    // Reference: 10..13 is ACG(ABC), 13..15 is TA(EF), 15..17 is deleted, 17..19 is GC(GH).
+    let qualities = record.base_qualities_at_ref(8, 21);
+    let expected: Vec<Option<u8>> = vec![
+        None,
+        None,
+        Some(32),
+        Some(33),
+        Some(34),
+        Some(36),
+        Some(37),
+        None,
+        None,
+        Some(38),
+        Some(39),
+        None,
+        None,
+    ];
+    assert_eq!(qualities, expected);
+    assert_eq!(record.base_qualities_at_ref(15, 17), vec![None, None]);
+    assert!(record.base_qualities_at_ref(12, 12).is_empty());
+    let line = "read1\t0\tchr1\t11\t60\t4M\t*\t0\t0\tACGT\t*";
+    let record: Record = line.parse().unwrap();
+    assert_eq!(record.base_qualities_at_ref(10, 12), vec![None, None]);
+}
+
+#[test]
+fn merge_all_test() {
+    let coverage = |lines: &[&str]| {
+        let records: Vec<Record> = lines.iter().map(|line| line.parse().unwrap()).collect();
+        Coverage::new(&records)
+    };
+    let sample1 = coverage(&[
+        "read1\t0\tchr1\t1\t60\t4M\t*\t0\t0\tACGT\tIIII\tNM:i:0",
+        "read2\t0\tchr2\t1\t60\t2M\t*\t0\t0\tAC\tII\tNM:i:0",
+    ]);
+    let sample2 = coverage(&["read3\t0\tchr1\t3\t60\t4M\t*\t0\t0\tGTAA\tIIII\tNM:i:0"]);
+    let sample3 = coverage(&[
+        "read4\t0\tchr1\t2\t60\t2M\t*\t0\t0\tCG\tII\tNM:i:0",
+        "read5\t0\tchr1\t4\t60\t1M\t*\t0\t0\tT\tI\tNM:i:0",
+    ]);
+    let merged = Coverage::merge_all(&[sample1, sample2, sample3]);
+    assert_eq!(merged.len(), 2);
+    assert_eq!(merged[0].r_name(), "chr1");
+    assert_eq!(
+        merged[0].cov(),
+        &[(1, 1), (2, 2), (3, 3), (4, 3), (5, 1), (6, 1)]
+    );
+    assert_eq!(merged[1].r_name(), "chr2");
+    assert_eq!(merged[1].cov(), &[(1, 1), (2, 1)]);
+    assert!(Coverage::merge_all(&[]).is_empty());
+}
+
+#[test]
+fn mark_duplicates_test() {
+    let mut records: Vec<Record> = [
+        "read1\t0\tchr1\t100\t30\t4M\t*\t0\t0\tACGT\tIIII",
+        "read2\t1024\tchr1\t100\t60\t4M\t*\t0\t0\tACGT\tIIII",
+        // Different strand, CIGAR, or position.
+        "read3\t16\tchr1\t100\t60\t4M\t*\t0\t0\tACGT\tIIII",
+        "read4\t0\tchr1\t100\t60\t2S2M\t*\t0\t0\tACGT\tIIII",
+        "read5\t0\tchr1\t101\t60\t4M\t*\t0\t0\tACGT\tIIII",
+        // Pairs with the same coordinates but different mates.
+        "pair1\t99\tchr1\t200\t60\t4M\t=\t300\t104\tACGT\tIIII",
+        "pair2\t99\tchr1\t200\t60\t4M\t=\t300\t104\tACGT\tIIII",
+        "pair3\t99\tchr1\t200\t60\t4M\tchr1\t400\t204\tACGT\tIIII",
+        // Secondary and unmapped records are never marked.
+        "read1\t256\tchr1\t100\t0\t4M\t*\t0\t0\tACGT\tIIII",
+        "read6\t4\t*\t0\t0\t*\t*\t0\t0\tACGT\tIIII",
+        "read7\t4\t*\t0\t0\t*\t*\t0\t0\tACGT\tIIII",
+    ]
+    .iter()
+    .map(|line| line.parse().unwrap())
+    .collect();
+    mark_duplicates(&mut records);
+    let is_duplicate: Vec<_> = records.iter().map(|r| r.flag() & 0x400 == 0x400).collect();
+    let expected = [
+        true, false, false, false, false, false, true, false, false, false, false,
+    ];
+    assert_eq!(is_duplicate, expected);
+    mark_duplicates(&mut records);
+    let is_duplicate: Vec<_> = records.iter().map(|r| r.flag() & 0x400 == 0x400).collect();
+    assert_eq!(is_duplicate, expected);
+}
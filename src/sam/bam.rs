@@ -0,0 +1,319 @@
+//! A reader of BAM, the binary counterpart of SAM.
+//! The BGZF blocks are decompressed as a multi-member gzip stream, so the index is not used.
+use super::{Header, Op, Record, Sam};
+use std::io;
+use std::io::Read;
+use std::path::Path;
+
+/// BAM reader yielding [`Record`]s. The header and the reference dictionary are read on creation.
+#[derive(Debug)]
+pub struct BamReader<R: Read> {
+    reader: flate2::read::MultiGzDecoder<R>,
+    headers: Vec<Header>,
+    references: Vec<(String, usize)>,
+}
+
+impl BamReader<std::fs::File> {
+    pub fn from_file<P: AsRef<Path>>(file: P) -> io::Result<Self> {
+        std::fs::File::open(file).and_then(Self::new)
+    }
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("invalid BAM: {}", message),
+    )
+}
+
+impl<R: Read> BamReader<R> {
+    pub fn new(reader: R) -> io::Result<Self> {
+        let mut reader = flate2::read::MultiGzDecoder::new(reader);
+        let mut magic = [0; 4];
+        reader.read_exact(&mut magic)?;
+        if &magic != b"BAM\x01" {
+            return Err(invalid("wrong magic number"));
+        }
+        let text_len = read_len(&mut reader)?;
+        let mut text = vec![0; text_len];
+        reader.read_exact(&mut text)?;
+        let text = String::from_utf8_lossy(&text);
+        let headers = text
+            .lines()
+            .map(|line| line.trim_end_matches('\0'))
+            .filter(|line| !line.is_empty())
+            .map(|line| Header::new(line).ok_or_else(|| invalid("header")))
+            .collect::<io::Result<Vec<_>>>()?;
+        let num_refs = read_len(&mut reader)?;
+        let mut references = Vec::with_capacity(num_refs);
+        for _ in 0..num_refs {
+            let mut name = vec![0; read_len(&mut reader)?];
+            reader.read_exact(&mut name)?;
+            let name = String::from_utf8_lossy(&name)
+                .trim_end_matches('\0')
+                .to_string();
+            references.push((name, read_len(&mut reader)?));
+        }
+        Ok(Self {
+            reader,
+            headers,
+            references,
+        })
+    }
+    /// The headers in the text part of the BAM.
+    pub fn headers(&self) -> &[Header] {
+        &self.headers
+    }
+    /// The names and the lengths of the references.
+    pub fn references(&self) -> &[(String, usize)] {
+        &self.references
+    }
+    /// Read the next record. Return `None` at the end of the file.
+    pub fn read_record(&mut self) -> io::Result<Option<Record>> {
+        let mut size = [0; 4];
+        let mut filled = 0;
+        while filled < 4 {
+            match self.reader.read(&mut size[filled..])? {
+                0 if filled == 0 => return Ok(None),
+                0 => return Err(io::Error::from(io::ErrorKind::UnexpectedEof)),
+                read => filled += read,
+            }
+        }
+        let size = i32::from_le_bytes(size);
+        if size < 0 {
+            return Err(invalid("negative block size"));
+        }
+        let mut block = vec![0; size as usize];
+        self.reader.read_exact(&mut block)?;
+        self.parse_record(&block).map(Some)
+    }
+    /// Iterate over the records. After an error is yielded, the iterator ends.
+    pub fn records(self) -> BamRecords<R> {
+        BamRecords {
+            inner: self,
+            has_error_occured: false,
+        }
+    }
+    /// Read all the records into a [`Sam`].
+    pub fn into_sam(self) -> io::Result<Sam> {
+        let headers = self.headers.clone();
+        let records = self.records().collect::<io::Result<Vec<_>>>()?;
+        Ok(Sam::new(headers, records))
+    }
+    fn reference_name(&self, id: i32) -> io::Result<String> {
+        match id {
+            -1 => Ok("*".to_string()),
+            _ => self
+                .references
+                .get(id as usize)
+                .map(|(name, _)| name.clone())
+                .ok_or_else(|| invalid("reference id out of range")),
+        }
+    }
+    fn parse_record(&self, block: &[u8]) -> io::Result<Record> {
+        let mut block = Cursor { buf: block, pos: 0 };
+        let ref_id = block.i32()?;
+        let pos = block.i32()?;
+        let name_len = block.u8()? as usize;
+        let mapq = block.u8()? as usize;
+        let _bin = block.u16()?;
+        let num_ops = block.u16()? as usize;
+        let flag = block.u16()? as u32;
+        let seq_len = block.i32()?.max(0) as usize;
+        let next_ref_id = block.i32()?;
+        let next_pos = block.i32()?;
+        let tlen = block.i32()? as i64;
+        let q_name = String::from_utf8_lossy(block.bytes(name_len)?)
+            .trim_end_matches('\0')
+            .to_string();
+        let mut cigar = String::new();
+        for _ in 0..num_ops {
+            let op = block.u32()?;
+            let kind = *b"MIDNSHP=X"
+                .get((op & 0xf) as usize)
+                .ok_or_else(|| invalid("CIGAR operation"))?;
+            cigar += &Op::from((op >> 4) as usize, kind).unwrap().as_str();
+        }
+        if cigar.is_empty() {
+            cigar.push('*');
+        }
+        let seq: String = block
+            .bytes(seq_len.div_ceil(2))?
+            .iter()
+            .flat_map(|&b| [b >> 4, b & 0xf])
+            .take(seq_len)
+            .map(|code| b"=ACMGRSVTWYHKDBN"[code as usize] as char)
+            .collect();
+        let qual = block.bytes(seq_len)?;
+        let qual = match qual.first() {
+            Some(&q) if q != 0xff => qual.to_vec(),
+            _ => vec![b'*' - 33],
+        };
+        let mut attr = vec![];
+        while block.pos < block.buf.len() {
+            attr.push(block.tag()?);
+        }
+        let r_name = self.reference_name(ref_id)?;
+        let rnext = match next_ref_id {
+            -1 => "*".to_string(),
+            id if id == ref_id => "=".to_string(),
+            id => self.reference_name(id)?,
+        };
+        Ok(Record {
+            q_name,
+            flag,
+            r_name,
+            pos: (pos + 1).max(0) as usize,
+            mapq,
+            cigar,
+            rnext,
+            pnext: (next_pos + 1).max(0) as usize,
+            tlen,
+            seq: if seq.is_empty() { "*".to_string() } else { seq },
+            qual,
+            attr,
+        })
+    }
+}
+
+/// Iterator over the records in a BAM. See [`BamReader::records`].
+#[derive(Debug)]
+pub struct BamRecords<R: Read> {
+    inner: BamReader<R>,
+    has_error_occured: bool,
+}
+
+impl<R: Read> Iterator for BamRecords<R> {
+    type Item = io::Result<Record>;
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.has_error_occured {
+            return None;
+        }
+        match self.inner.read_record() {
+            Ok(record) => record.map(Ok),
+            Err(why) => {
+                self.has_error_occured = true;
+                Some(Err(why))
+            }
+        }
+    }
+}
+
+fn read_len<R: Read>(reader: &mut R) -> io::Result<usize> {
+    let mut buf = [0; 4];
+    reader.read_exact(&mut buf)?;
+    match i32::from_le_bytes(buf) {
+        len if len < 0 => Err(invalid("negative length")),
+        len => Ok(len as usize),
+    }
+}
+
+// Little-endian cursor over a record block.
+struct Cursor<'a> {
+    buf: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Cursor<'a> {
+    fn bytes(&mut self, len: usize) -> io::Result<&'a [u8]> {
+        let bytes = self
+            .buf
+            .get(self.pos..self.pos + len)
+            .ok_or_else(|| invalid("truncated record"))?;
+        self.pos += len;
+        Ok(bytes)
+    }
+    fn array<const N: usize>(&mut self) -> io::Result<[u8; N]> {
+        let mut array = [0; N];
+        array.copy_from_slice(self.bytes(N)?);
+        Ok(array)
+    }
+    fn u8(&mut self) -> io::Result<u8> {
+        self.array::<1>().map(|x| x[0])
+    }
+    fn u16(&mut self) -> io::Result<u16> {
+        self.array().map(u16::from_le_bytes)
+    }
+    fn i32(&mut self) -> io::Result<i32> {
+        self.array().map(i32::from_le_bytes)
+    }
+    fn u32(&mut self) -> io::Result<u32> {
+        self.array().map(u32::from_le_bytes)
+    }
+    fn null_terminated(&mut self) -> io::Result<String> {
+        let len = self.buf[self.pos..]
+            .iter()
+            .position(|&b| b == 0)
+            .ok_or_else(|| invalid("unterminated string"))?;
+        let string = String::from_utf8_lossy(self.bytes(len)?).to_string();
+        self.pos += 1;
+        Ok(string)
+    }
+    // A value of the numeric type, formatted as in SAM.
+    fn number(&mut self, value_type: u8) -> io::Result<String> {
+        let value = match value_type {
+            b'c' => (self.u8()? as i8).to_string(),
+            b'C' => self.u8()?.to_string(),
+            b's' => i16::from_le_bytes(self.array()?).to_string(),
+            b'S' => self.u16()?.to_string(),
+            b'i' => self.i32()?.to_string(),
+            b'I' => self.u32()?.to_string(),
+            b'f' => f32::from_le_bytes(self.array()?).to_string(),
+            _ => return Err(invalid("tag type")),
+        };
+        Ok(value)
+    }
+    // An optional field, formatted as "[TAG]:[TYPE]:[VALUE]".
+    fn tag(&mut self) -> io::Result<String> {
+        let tag = String::from_utf8_lossy(self.bytes(2)?).to_string();
+        let value_type = self.u8()?;
+        let (sam_type, value) = match value_type {
+            b'A' => ('A', (self.u8()? as char).to_string()),
+            b'c' | b'C' | b's' | b'S' | b'i' | b'I' => ('i', self.number(value_type)?),
+            b'f' => ('f', self.number(value_type)?),
+            b'Z' => ('Z', self.null_terminated()?),
+            b'H' => ('H', self.null_terminated()?),
+            b'B' => {
+                let sub_type = self.u8()?;
+                let len = self.i32()?.max(0);
+                let mut value = (sub_type as char).to_string();
+                for _ in 0..len {
+                    value.push(',');
+                    value += &self.number(sub_type)?;
+                }
+                ('B', value)
+            }
+            _ => return Err(invalid("tag type")),
+        };
+        Ok(format!("{}:{}:{}", tag, sam_type, value))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn bam_matches_sam() {
+        let file = std::fs::File::open("./testdata/test_bam.sam").unwrap();
+        let sam = Sam::from_reader(io::BufReader::new(file)).unwrap();
+        let reader = BamReader::from_file("./testdata/test.bam").unwrap();
+        assert_eq!(
+            reader.references(),
+            &[("chr1".to_string(), 100), ("chr2".to_string(), 50)]
+        );
+        let bam = reader.into_sam().unwrap();
+        let to_lines = |sam: &Sam| {
+            let mut output = vec![];
+            sam.write(&mut output).unwrap();
+            String::from_utf8(output).unwrap()
+        };
+        assert_eq!(to_lines(&bam), to_lines(&sam));
+        assert_eq!(bam.records[1].get_tag("XA"), Some(("Z", "chr2,+5,8M,0")));
+    }
+    #[test]
+    fn not_bam() {
+        let gz = std::fs::read("./testdata/test.bam").unwrap();
+        assert!(BamReader::new(&gz[..20]).is_err());
+        assert!(BamReader::new(&b"BAM\x01"[..]).is_err());
+    }
+}